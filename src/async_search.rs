@@ -0,0 +1,180 @@
+// src/async_search.rs
+use crate::{engine_search, expected_reply, Board, Color, Engine, SearchShared, Square};
+#[cfg(test)]
+use crate::{MAX_DEPTH, MAX_PLY};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+pub enum SearchEvent {
+    // An iteration finished; with MultiPV there is one event per line
+    Iteration {
+        depth: i32,
+        multipv: usize,
+        score: i32,
+        nodes: u64,
+        elapsed: Duration,
+        pv: Vec<(Square, Square)>,
+    },
+    // Sent periodically while an iteration is running
+    Progress {
+        depth: i32,
+        nodes: u64,
+        elapsed: Duration,
+        current_move: (Square, Square),
+        current_move_number: usize,
+    },
+    // Last event of a search, same value as returned by `wait`
+    Finished(SearchResult),
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchLimits {
+    pub depth: i32,
+    // Keep searching until `ponderhit` or `stop`
+    pub ponder: bool,
+    // Keep searching until `stop`
+    pub infinite: bool,
+    pub nodes: Option<u64>,
+    // Time for the move, counted from the start or, when pondering, from
+    // `ponderhit`
+    pub movetime: Option<Duration>,
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchResult {
+    pub best_move: Option<(Square, Square)>,
+    pub ponder_move: Option<(Square, Square)>,
+}
+
+// A search running on a worker thread
+pub struct SearchHandle {
+    shared: Arc<SearchShared>,
+    handle: thread::JoinHandle<SearchResult>,
+}
+
+impl SearchHandle {
+    pub fn start<F>(engine: &Engine, board: &Board, color: Color, limits: SearchLimits, callback: F) -> SearchHandle
+    where
+        F: FnMut(&SearchEvent) + Send + 'static,
+    {
+        let shared = Arc::new(SearchShared::new(&engine.search_limits(limits)));
+        let thread_shared = Arc::clone(&shared);
+        let engine = engine.clone();
+        let board = board.clone();
+        let mut callback = callback;
+
+        let handle = thread::spawn(move || {
+            let best_move = engine_search(&engine, &board, color, &thread_shared, Some(&mut callback));
+
+            // A ponder or infinite search is not over until we are told so
+            while thread_shared.pondering.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            let ponder_move = best_move.and_then(|(from, to)| {
+                let mut after = board.clone();
+                after.make_move(from, to);
+                expected_reply(&after, color.opposite(), &engine.tt)
+            });
+            let result = SearchResult { best_move, ponder_move };
+            callback(&SearchEvent::Finished(result));
+            result
+        });
+
+        SearchHandle { shared, handle }
+    }
+
+    pub fn stop(&self) {
+        self.shared.stop();
+    }
+
+    pub fn ponderhit(&self) {
+        self.shared.ponderhit();
+    }
+
+    // Blocks until the search has finished
+    pub fn wait(self) -> SearchResult {
+        self.handle.join().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn start_position() -> Board {
+        let mut board = Board::new();
+        board.setup_starting_position();
+        board
+    }
+
+    fn limits(depth: i32, ponder: bool, infinite: bool) -> SearchLimits {
+        SearchLimits { depth, ponder, infinite, nodes: None, movetime: None }
+    }
+
+    // Starts a search that sends the depth of each iteration, then None
+    // when it finishes
+    fn start(limits: SearchLimits) -> (SearchHandle, mpsc::Receiver<Option<i32>>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = SearchHandle::start(&Engine::new(), &start_position(), Color::White, limits, move |event| {
+            let _ = match event {
+                SearchEvent::Iteration { depth, .. } => sender.send(Some(*depth)),
+                SearchEvent::Finished(_) => sender.send(None),
+                SearchEvent::Progress { .. } => Ok(()),
+            };
+        });
+        (handle, receiver)
+    }
+
+    fn is_legal(mv: Option<(Square, Square)>) -> bool {
+        let board = start_position();
+        mv.is_some_and(|(from, to)| {
+            board.get_piece(from).is_some_and(|p| p.color == Color::White)
+                && board.generate_legal_moves_for_piece(from).contains(&to)
+        })
+    }
+
+    #[test]
+    fn stop_ends_an_infinite_search() {
+        let (handle, receiver) = start(limits(MAX_DEPTH, false, true));
+        assert_eq!(receiver.recv().unwrap(), Some(1));
+        handle.stop();
+        assert!(is_legal(handle.wait().best_move));
+        assert!(receiver.iter().any(|message| message.is_none()));
+    }
+
+    #[test]
+    fn iterations_come_in_increasing_depth() {
+        let (handle, receiver) = start(limits(3, false, false));
+        let result = handle.wait();
+        let messages: Vec<Option<i32>> = receiver.iter().collect();
+        assert_eq!(messages, [Some(1), Some(2), Some(3), None]);
+        assert!(is_legal(result.best_move));
+    }
+
+    #[test]
+    fn a_ponder_search_waits_for_ponderhit() {
+        let (handle, receiver) = start(limits(2, true, false));
+        assert_eq!(receiver.recv().unwrap(), Some(1));
+        assert_eq!(receiver.recv().unwrap(), Some(2));
+        // Past its depth, but still pondering
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        handle.ponderhit();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+        assert!(is_legal(handle.wait().best_move));
+    }
+
+    #[test]
+    fn movetime_stops_the_search() {
+        let mut limits = limits(MAX_PLY, false, false);
+        limits.movetime = Some(Duration::from_millis(200));
+        let (handle, receiver) = start(limits);
+        let started = std::time::Instant::now();
+        assert!(is_legal(handle.wait().best_move));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(receiver.iter().last(), Some(None));
+    }
+}
@@ -0,0 +1,204 @@
+// src/bin/epdtest.rs
+//
+// Runs an EPD test suite (see src/epd.rs). Every position is searched with
+// a depth or a time limit, or both and whichever comes first, on a cleared
+// hash table. The engine's move solves the position if it is one of the bm
+// moves and none of the am moves, and with dm n its score must also be a
+// mate within 2n - 1 plies. Each position gets a line with its
+// result and the totals come last. Positions with moves the engine cannot
+// play (underpromotions) or nothing to check are skipped.
+//
+// Usage: epdtest <suite.epd> [--depth <n>] [--time <ms>] [--threads <n>]
+//                [--hash <mb>]
+use rust_chess_engine::async_search::{SearchEvent, SearchHandle, SearchLimits};
+use rust_chess_engine::epd::Epd;
+use rust_chess_engine::{is_mate_score, mate_plies, san, Engine, Square, MAX_DEPTH, MAX_PLY};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+struct Options {
+    suite: String,
+    depth: Option<i32>,
+    time: Option<Duration>,
+    threads: usize,
+    hash_mb: usize,
+}
+
+// Last completed depth and its score
+#[derive(Default)]
+struct Report {
+    depth: i32,
+    score: i32,
+}
+
+// What a record asks of the engine's move
+struct Target {
+    best: Vec<(Square, Square)>,
+    avoid: Vec<(Square, Square)>,
+    mate: Option<u32>,
+}
+
+fn parse_args() -> Option<Options> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options { suite: String::new(), depth: None, time: None, threads: 1, hash_mb: 16 };
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match (args[i].as_str(), value) {
+            ("--depth", Some(v)) => options.depth = Some(v.parse().ok().filter(|&d| (1..=MAX_PLY).contains(&d))?),
+            ("--time", Some(v)) => options.time = Some(Duration::from_millis(v.parse().ok()?)),
+            ("--threads", Some(v)) => options.threads = v.parse().ok().filter(|&n| n > 0)?,
+            ("--hash", Some(v)) => options.hash_mb = v.parse().ok().filter(|&n| n > 0)?,
+            (arg, _) if !arg.starts_with("--") && options.suite.is_empty() => {
+                options.suite = arg.to_string();
+                i += 1;
+                continue;
+            }
+            _ => return None,
+        }
+        i += 2;
+    }
+    if options.suite.is_empty() { None } else { Some(options) }
+}
+
+fn search(engine: &Engine, epd: &Epd, options: &Options) -> (Option<(Square, Square)>, Report) {
+    let limits = SearchLimits {
+        // With only a time limit the clock ends the search
+        depth: options.depth.unwrap_or(if options.time.is_some() { MAX_PLY } else { MAX_DEPTH }),
+        ponder: false,
+        infinite: false,
+        nodes: None,
+        movetime: None,
+    };
+    // Iterations of the first line as (depth, score); None once the search is over
+    let (sender, receiver) = mpsc::channel();
+    let handle = SearchHandle::start(engine, &epd.board, epd.color, limits, move |event| {
+        let _ = match *event {
+            SearchEvent::Iteration { depth, multipv: 1, score, .. } => sender.send(Some((depth, score))),
+            SearchEvent::Finished(_) => sender.send(None),
+            _ => Ok(()),
+        };
+    });
+
+    // Until the deadline wait for it, after it for the search to wind down
+    let mut deadline = options.time.map(|time| Instant::now() + time);
+    let mut report = Report::default();
+    loop {
+        let message = match deadline {
+            Some(time) => match receiver.recv_timeout(time.saturating_duration_since(Instant::now())) {
+                Ok(message) => message,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    handle.stop();
+                    deadline = None;
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => None,
+            },
+            None => receiver.recv().ok().flatten(),
+        };
+        let Some((depth, score)) = message else { break };
+        report.depth = depth;
+        report.score = score;
+    }
+    (handle.wait().best_move, report)
+}
+
+// The record's target, or why the position cannot be tried
+fn target(epd: &Epd) -> Result<Target, String> {
+    let best = epd.best_moves().map_err(|e| e.to_string())?;
+    let avoid = epd.avoid_moves().map_err(|e| e.to_string())?;
+    let mate = epd.direct_mate();
+    if best.is_empty() && avoid.is_empty() && mate.is_none() {
+        return Err("no bm, am or dm".to_string());
+    }
+    Ok(Target { best, avoid, mate })
+}
+
+fn is_solved(target: &Target, best_move: Option<(Square, Square)>, report: &Report) -> bool {
+    let Some(mv) = best_move else { return false };
+    (target.best.is_empty() || target.best.contains(&mv))
+        && !target.avoid.contains(&mv)
+        && target.mate.is_none_or(|n| {
+            report.score > 0 && is_mate_score(report.score) && mate_plies(report.score) < 2 * n as i32
+        })
+}
+
+// What the record asks for, e.g. "bm Qg6 Rxf7; dm 3"
+fn expected(epd: &Epd) -> String {
+    let wanted: Vec<String> = ["bm", "am", "dm"]
+        .iter()
+        .filter_map(|&opcode| Some(format!("{} {}", opcode, epd.operands(opcode)?.join(" "))))
+        .collect();
+    wanted.join("; ")
+}
+
+fn main() {
+    let Some(options) = parse_args() else {
+        eprintln!("Usage: epdtest <suite.epd> [--depth <n>] [--time <ms>] [--threads <n>] [--hash <mb>]");
+        return;
+    };
+    let text = match std::fs::read_to_string(&options.suite) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", options.suite, e);
+            return;
+        }
+    };
+
+    let mut engine = Engine::new();
+    engine.threads = options.threads;
+    let start = Instant::now();
+    let (mut solved, mut failed, mut skipped) = (0, 0, 0);
+    for (number, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#')) {
+        let epd = match Epd::parse(line) {
+            Ok(epd) => epd,
+            Err(e) => {
+                println!("line {}: skipped, {}", number + 1, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        let id = epd.id().map_or_else(|| format!("line {}", number + 1), String::from);
+        let target = match target(&epd) {
+            Ok(target) => target,
+            Err(e) => {
+                println!("{}: skipped, {} ({})", id, e, expected(&epd));
+                skipped += 1;
+                continue;
+            }
+        };
+
+        // Every position starts from an empty hash table
+        engine.resize_hash(options.hash_mb);
+        let searched = Instant::now();
+        let (best_move, report) = search(&engine, &epd, &options);
+        let played = best_move.map_or("none".to_string(), |mv| san::to_san(&epd.board, epd.color, mv));
+        let result = if is_solved(&target, best_move, &report) {
+            solved += 1;
+            "solved"
+        } else {
+            failed += 1;
+            "failed"
+        };
+        println!(
+            "{}: {} - played {} ({}), depth {}, score {}, {:.2}s",
+            id,
+            result,
+            played,
+            expected(&epd),
+            report.depth,
+            report.score,
+            searched.elapsed().as_secs_f64()
+        );
+    }
+
+    let tried = solved + failed;
+    println!(
+        "Solved {} of {} ({:.1}%), {} skipped, {:.1}s",
+        solved,
+        tried,
+        if tried > 0 { 100.0 * solved as f64 / tried as f64 } else { 0.0 },
+        skipped,
+        start.elapsed().as_secs_f64()
+    );
+}
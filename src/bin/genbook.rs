@@ -0,0 +1,200 @@
+// src/bin/genbook.rs
+//
+// Builds a Polyglot opening book (see src/book.rs) from PGN game
+// collections. Every game that passes the filters is replayed from the
+// starting position and each of its first plies is counted for the position
+// it was played in, together with the game's result. A move's weight is two
+// points per win and one per draw for the side that played it, scaled down
+// when the largest weight does not fit the book's 16 bits.
+//
+// Usage: genbook --output <file> [--min-elo <n>] [--results <list>]
+//                [--max-ply <n>] [--min-games <n>] <pgn>...
+//
+// --min-elo skips games unless both players are rated at least that much,
+// --results keeps only games with the listed results (comma separated, from
+// 1-0, 0-1 and 1/2-1/2; all three by default), --max-ply sets how deep into
+// the games moves are taken (default 30) and --min-games drops moves played
+// in fewer games than that (default 3). Games that start from a set-up
+// position, have no result or cannot be read (see src/pgn.rs) are skipped.
+use rust_chess_engine::book::Entry;
+use rust_chess_engine::pgn::{self, Game};
+use rust_chess_engine::{polyglot, Color};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+const RESULTS: [&str; 3] = ["1-0", "0-1", "1/2-1/2"];
+
+struct Options {
+    output: String,
+    min_elo: Option<u32>,
+    results: Vec<String>,
+    max_ply: usize,
+    min_games: u32,
+    pgns: Vec<String>,
+}
+
+// Games a move was played in, by result for the side that played it
+#[derive(Default)]
+struct Stats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Stats {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn score(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+fn parse_args() -> Option<Options> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options {
+        output: String::new(),
+        min_elo: None,
+        results: RESULTS.iter().map(|r| r.to_string()).collect(),
+        max_ply: 30,
+        min_games: 3,
+        pgns: Vec::new(),
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match (args[i].as_str(), value) {
+            ("--output", Some(v)) => options.output = v.clone(),
+            ("--min-elo", Some(v)) => options.min_elo = Some(v.parse().ok()?),
+            ("--max-ply", Some(v)) => options.max_ply = v.parse().ok().filter(|&n| n > 0)?,
+            ("--min-games", Some(v)) => options.min_games = v.parse().ok().filter(|&n| n > 0)?,
+            ("--results", Some(v)) => {
+                options.results = v.split(',').map(|r| r.trim().to_string()).collect();
+                if !options.results.iter().all(|r| RESULTS.contains(&r.as_str())) {
+                    return None;
+                }
+            }
+            (arg, _) if !arg.starts_with("--") => {
+                options.pgns.push(arg.to_string());
+                i += 1;
+                continue;
+            }
+            _ => return None,
+        }
+        i += 2;
+    }
+    if options.output.is_empty() || options.pgns.is_empty() { None } else { Some(options) }
+}
+
+fn rating(game: &Game, tag: &str) -> Option<u32> {
+    game.tag(tag)?.parse().ok()
+}
+
+fn accepted(game: &Game, options: &Options) -> bool {
+    let Some(result) = game.tag("Result") else { return false };
+    if !options.results.iter().any(|r| r == result) || game.tag("FEN").is_some() {
+        return false;
+    }
+    match options.min_elo {
+        Some(min) => ["WhiteElo", "BlackElo"].iter().all(|tag| rating(game, tag).is_some_and(|r| r >= min)),
+        None => true,
+    }
+}
+
+// Counts the first moves of the game's main line
+fn add_game(game: &mut Game, options: &Options, stats: &mut HashMap<(u64, u16), Stats>) {
+    let result = game.tag("Result").unwrap_or("*").to_string();
+    game.go_to(0);
+    for _ in 0..options.max_ply {
+        let (board, color) = game.position();
+        let Some(&next) = game.node(game.current()).children.first() else { break };
+        let Some(mv) = game.node(next).mv else { break };
+        let entry = stats.entry((polyglot::key(board, color), polyglot::encode_move(board, mv))).or_default();
+        match (result.as_str(), color) {
+            ("1/2-1/2", _) => entry.draws += 1,
+            ("1-0", Color::White) | ("0-1", Color::Black) => entry.wins += 1,
+            _ => entry.losses += 1,
+        }
+        game.forward(0);
+    }
+}
+
+// Book entries, sorted by key and within a position heaviest first
+fn make_entries(stats: &HashMap<(u64, u16), Stats>, min_games: u32) -> Vec<Entry> {
+    let kept: Vec<_> = stats.iter().filter(|(_, s)| s.games() >= min_games).collect();
+    let max_score = kept.iter().map(|(_, s)| s.score()).max().unwrap_or(0);
+    let scale = |score: u64| {
+        if max_score <= u16::MAX as u64 {
+            score as u16
+        } else {
+            // Keep moves that scored at all playable
+            (score * u16::MAX as u64 / max_score).max(score.min(1)) as u16
+        }
+    };
+    let mut entries: Vec<Entry> = kept
+        .iter()
+        .map(|&(&(key, mv), s)| Entry { key, mv, weight: scale(s.score()), learn: 0 })
+        .collect();
+    entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight), e.mv));
+    entries
+}
+
+fn main() {
+    let Some(options) = parse_args() else {
+        eprintln!(
+            "Usage: genbook --output <file> [--min-elo <n>] [--results <list>] [--max-ply <n>] [--min-games <n>] \
+             <pgn>..."
+        );
+        return;
+    };
+
+    let start = Instant::now();
+    let mut stats = HashMap::new();
+    let (mut read, mut used, mut broken) = (0, 0, 0);
+    for path in &options.pgns {
+        // PGN files in the wild are not always UTF-8; only ASCII matters here
+        let text = match std::fs::read(path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                eprintln!("Cannot read {}: {}", path, e);
+                return;
+            }
+        };
+        for game in pgn::parse(&text) {
+            read += 1;
+            match game {
+                Ok(mut game) if accepted(&game, &options) => {
+                    used += 1;
+                    add_game(&mut game, &options, &mut stats);
+                }
+                Ok(_) => {}
+                Err(_) => broken += 1,
+            }
+        }
+    }
+
+    let entries = make_entries(&stats, options.min_games);
+    let write = || -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(&options.output)?);
+        for entry in &entries {
+            out.write_all(&entry.to_bytes())?;
+        }
+        out.flush()
+    };
+    if let Err(e) = write() {
+        eprintln!("Cannot write {}: {}", options.output, e);
+        return;
+    }
+    let positions = entries.chunk_by(|a, b| a.key == b.key).count();
+    println!("Read {} games, used {} ({} could not be read)", read, used, broken);
+    println!(
+        "Wrote {} moves for {} positions to {} ({:.1}s)",
+        entries.len(),
+        positions,
+        options.output,
+        start.elapsed().as_secs_f64()
+    );
+}
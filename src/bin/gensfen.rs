@@ -0,0 +1,282 @@
+// src/bin/gensfen.rs
+//
+// Generates training data for NNUE networks from self-play. Every game starts
+// with a few random moves, then both sides play the move found by a fixed
+// depth (or fixed node) search. Quiet positions are recorded with the search
+// score and, once the game is over, its result.
+//
+// Usage: gensfen --output <file> [--games <n>] [--depth <n>] [--nodes <n>]
+//                [--random-plies <n>] [--format binary|text] [--threads <n>]
+use rand::seq::SliceRandom;
+use rust_chess_engine::async_search::SearchLimits;
+use rust_chess_engine::tt::TranspositionTable;
+use rust_chess_engine::{
+    is_in_check, search_multipv, Board, Color, PieceType, SearchShared, Square, MAX_DEPTH, MAX_PLY,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+// Games that go on longer are scored as draws; there is no repetition or
+// fifty-move detection to end them earlier
+const MAX_GAME_PLIES: usize = 400;
+// A search score this large decides the game for the side it favours
+const ADJUDICATE_SCORE: i32 = 3000;
+const HASH_MB: usize = 16;
+// Size of one record in the binary format
+const RECORD_SIZE: usize = 32;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Binary,
+    Text,
+}
+
+struct Options {
+    output: String,
+    games: usize,
+    depth: i32,
+    nodes: Option<u64>,
+    random_plies: usize,
+    format: Format,
+    threads: usize,
+}
+
+struct Entry {
+    board: Board,
+    side_to_move: Color,
+    // From the side to move's point of view
+    score: i32,
+    ply: usize,
+}
+
+fn parse_args() -> Option<Options> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options {
+        output: String::new(),
+        games: 100,
+        depth: MAX_DEPTH,
+        nodes: None,
+        random_plies: 8,
+        format: Format::Binary,
+        threads: 1,
+    };
+    for pair in args.chunks(2) {
+        let [name, value] = pair else { return None };
+        match name.as_str() {
+            "--output" => options.output = value.clone(),
+            "--games" => options.games = value.parse().ok()?,
+            "--depth" => options.depth = value.parse().ok().filter(|&d| d > 0)?,
+            "--nodes" => options.nodes = Some(value.parse().ok()?),
+            "--random-plies" => options.random_plies = value.parse().ok()?,
+            "--threads" => options.threads = value.parse().ok().filter(|&n| n > 0)?,
+            "--format" => {
+                options.format = match value.as_str() {
+                    "binary" => Format::Binary,
+                    "text" => Format::Text,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    // A node limit alone should not be cut short by the default depth
+    if options.nodes.is_some() && !args.iter().any(|a| a == "--depth") {
+        options.depth = MAX_PLY;
+    }
+    if options.output.is_empty() { None } else { Some(options) }
+}
+
+fn legal_moves(board: &Board, color: Color) -> Vec<(Square, Square)> {
+    let mut moves = Vec::new();
+    for from in 0u8..128 {
+        if let Some(piece) = board.get_piece(from)
+            && piece.color == color
+        {
+            moves.extend(board.generate_legal_moves_for_piece(from).into_iter().map(|to| (from, to)));
+        }
+    }
+    moves
+}
+
+fn only_kings(board: &Board) -> bool {
+    (0u8..128).all(|sq| board.get_piece(sq).is_none_or(|p| p.kind == PieceType::King))
+}
+
+// The start position after `plies` random moves; None if the game ended on the way
+fn random_opening(plies: usize) -> Option<(Board, Color)> {
+    let mut rng = rand::thread_rng();
+    let mut board = Board::new();
+    board.setup_starting_position();
+    let mut color = Color::White;
+    for _ in 0..plies {
+        let &(from, to) = legal_moves(&board, color).choose(&mut rng)?;
+        board.make_move(from, to);
+        color = color.opposite();
+    }
+    (!legal_moves(&board, color).is_empty()).then_some((board, color))
+}
+
+// Plays one game; returns the recorded positions and the result from
+// White's point of view (1.0 win, 0.5 draw, 0.0 loss)
+fn play_game(options: &Options, tt: &TranspositionTable) -> (Vec<Entry>, f64) {
+    let (mut board, mut color) = loop {
+        if let Some(start) = random_opening(options.random_plies) {
+            break start;
+        }
+    };
+    let mut entries = Vec::new();
+
+    let winner = |color: Color| if color == Color::White { 1.0 } else { 0.0 };
+    for ply in options.random_plies..MAX_GAME_PLIES {
+        if only_kings(&board) {
+            return (entries, 0.5);
+        }
+        if legal_moves(&board, color).is_empty() {
+            let result = if is_in_check(&board, color) { winner(color.opposite()) } else { 0.5 };
+            return (entries, result);
+        }
+
+        let shared = SearchShared::new(&SearchLimits {
+            depth: options.depth,
+            ponder: false,
+            infinite: false,
+            nodes: options.nodes,
+            movetime: None,
+        });
+        let results = search_multipv(&board, color, 1, tt, &shared, None);
+        let Some(line) = results.last().and_then(|lines| lines.first()) else {
+            return (entries, 0.5);
+        };
+        if line.score >= ADJUDICATE_SCORE {
+            return (entries, winner(color));
+        }
+        if line.score <= -ADJUDICATE_SCORE {
+            return (entries, winner(color.opposite()));
+        }
+
+        // Only quiet positions: not in check and the best move is no
+        // capture, en passant included
+        let (from, to) = line.best_move;
+        let en_passant = board.en_passant == Some(to)
+            && from & 7 != to & 7
+            && board.get_piece(from).is_some_and(|p| p.kind == PieceType::Pawn);
+        if !is_in_check(&board, color) && board.get_piece(to).is_none() && !en_passant {
+            entries.push(Entry {
+                board: board.clone(),
+                side_to_move: color,
+                score: line.score,
+                ply,
+            });
+        }
+        board.make_move(from, to);
+        color = color.opposite();
+    }
+    (entries, 0.5)
+}
+
+// 32 bytes per position, all integers little-endian:
+//   0..8    occupancy, bit 0 = a1 .. bit 63 = h8
+//   8..24   4-bit piece codes (colour * 6 + piece type) of the occupied
+//           squares in bit order, low nibble first
+//   24      side to move, 0 White, 1 Black
+//   25..27  search score, i16, side to move's point of view
+//   27      result, i8, side to move's point of view: 1 win, 0 draw, -1 loss
+//   28..30  ply, u16
+//   30..32  reserved, zero
+fn encode(entry: &Entry, white_result: f64) -> [u8; RECORD_SIZE] {
+    let mut record = [0u8; RECORD_SIZE];
+    let mut occupancy = 0u64;
+    let mut count = 0;
+    for bit in 0..64u8 {
+        let sq = (7 - bit / 8) * 16 + bit % 8;
+        if let Some(piece) = entry.board.get_piece(sq)
+            && count < 32
+        {
+            occupancy |= 1 << bit;
+            let code = piece.color as u8 * 6 + piece.kind as u8;
+            record[8 + count / 2] |= code << (4 * (count % 2));
+            count += 1;
+        }
+    }
+    record[0..8].copy_from_slice(&occupancy.to_le_bytes());
+    record[24] = entry.side_to_move as u8;
+    let score = entry.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    record[25..27].copy_from_slice(&score.to_le_bytes());
+    let white_result = (white_result * 2.0) as i8 - 1;
+    let result = if entry.side_to_move == Color::White { white_result } else { -white_result };
+    record[27] = result as u8;
+    record[28..30].copy_from_slice(&(entry.ply.min(u16::MAX as usize) as u16).to_le_bytes());
+    record
+}
+
+// "<fen> | <score> | <result>" with score and result from White's point of
+// view, result 1.0, 0.5 or 0.0
+fn text_line(entry: &Entry, white_result: f64) -> String {
+    let score = if entry.side_to_move == Color::White { entry.score } else { -entry.score };
+    format!("{} | {} | {:.1}", entry.board.to_fen(entry.side_to_move), score, white_result)
+}
+
+fn main() {
+    let Some(options) = parse_args() else {
+        eprintln!(
+            "Usage: gensfen --output <file> [--games <n>] [--depth <n>] [--nodes <n>] \
+             [--random-plies <n>] [--format binary|text] [--threads <n>]"
+        );
+        return;
+    };
+    let file = match File::create(&options.output) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Cannot create {}: {}", options.output, e);
+            return;
+        }
+    };
+
+    let writer = Mutex::new(BufWriter::new(file));
+    let started = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        for _ in 0..options.threads {
+            s.spawn(|| {
+                let tt = TranspositionTable::new(HASH_MB);
+                while started.fetch_add(1, Ordering::SeqCst) < options.games {
+                    let (entries, result) = play_game(&options, &tt);
+
+                    let mut out = writer.lock().unwrap();
+                    for entry in &entries {
+                        let written = match options.format {
+                            Format::Binary => out.write_all(&encode(entry, result)),
+                            Format::Text => writeln!(out, "{}", text_line(entry, result)),
+                        };
+                        if let Err(e) = written {
+                            eprintln!("Cannot write to {}: {}", options.output, e);
+                            std::process::exit(1);
+                        }
+                    }
+                    let total = positions.fetch_add(entries.len(), Ordering::SeqCst) + entries.len();
+                    let games = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                    if games.is_multiple_of(10) || games == options.games {
+                        println!(
+                            "{} / {} games, {} positions, {:.0}s",
+                            games,
+                            options.games,
+                            total,
+                            start.elapsed().as_secs_f64()
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    if let Err(e) = writer.lock().unwrap().flush() {
+        eprintln!("Cannot write to {}: {}", options.output, e);
+    }
+}
@@ -0,0 +1,106 @@
+// src/bin/gentb.rs
+//
+// Generates the engine's own distance-to-mate tablebases (see src/dtm.rs)
+// for endgames of up to four pieces. Tables are written to the output
+// directory as <material>.dtm; the tables a requested one depends on (by
+// captures and promotions) are generated too, or read back if the
+// directory already has them.
+//
+// Usage: gentb --output <dir> [--pieces <n>] [<material>...]
+//
+// Without a material list every endgame with 3 to --pieces pieces
+// (default 4) is generated.
+use rust_chess_engine::dtm::{self, Material};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+struct Options {
+    output: PathBuf,
+    pieces: usize,
+    materials: Vec<Material>,
+}
+
+fn parse_args() -> Option<Options> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options { output: PathBuf::new(), pieces: dtm::MAX_PIECES, materials: Vec::new() };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--output" => {
+                options.output = PathBuf::from(args.get(i + 1)?);
+                i += 1;
+            }
+            "--pieces" => {
+                options.pieces = args.get(i + 1)?.parse().ok().filter(|n| (3..=dtm::MAX_PIECES).contains(n))?;
+                i += 1;
+            }
+            name => options.materials.push(Material::parse(name)?),
+        }
+        i += 1;
+    }
+    if options.output.as_os_str().is_empty() { None } else { Some(options) }
+}
+
+// Generates (or loads) `material` after everything it depends on
+fn build(material: &Material, options: &Options, done: &mut HashMap<String, Vec<u8>>) -> bool {
+    let name = material.name();
+    if done.contains_key(&name) {
+        return true;
+    }
+    for successor in material.successors() {
+        if !build(&successor, options, done) {
+            return false;
+        }
+    }
+
+    let path = options.output.join(format!("{}.dtm", name));
+    if let Ok((stored, table)) = dtm::read_table(&path)
+        && stored == *material
+    {
+        println!("{}: already generated", name);
+        done.insert(name, table);
+        return true;
+    }
+
+    let start = Instant::now();
+    let table = dtm::generate(material, done);
+    if let Err(e) = dtm::write_table(&path, material, &table) {
+        eprintln!("Cannot write {}: {}", path.display(), e);
+        return false;
+    }
+    let longest = table.iter().filter(|&&b| b % 2 == 1).max().copied().unwrap_or(0);
+    let wins = table.iter().filter(|&&b| b % 2 == 1).count();
+    let losses = table.iter().filter(|&&b| b != 0 && b % 2 == 0).count();
+    println!(
+        "{}: {} wins, {} losses for the side to move, longest mate {} plies ({:.1}s)",
+        name,
+        wins,
+        losses,
+        longest,
+        start.elapsed().as_secs_f64()
+    );
+    done.insert(name, table);
+    true
+}
+
+fn main() {
+    let Some(mut options) = parse_args() else {
+        eprintln!("Usage: gentb --output <dir> [--pieces <n>] [<material>...]");
+        return;
+    };
+    if let Err(e) = std::fs::create_dir_all(&options.output) {
+        eprintln!("Cannot create {}: {}", options.output.display(), e);
+        return;
+    }
+    if options.materials.is_empty() {
+        options.materials = dtm::all_materials(options.pieces);
+    }
+
+    let mut done = HashMap::new();
+    for material in &options.materials {
+        if !build(material, &options, &mut done) {
+            return;
+        }
+    }
+}
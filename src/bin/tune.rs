@@ -0,0 +1,370 @@
+// src/bin/tune.rs
+//
+// Texel tuning of the evaluation parameters. Reads positions labelled with
+// the game result, resolves captures with a quiescence search, fits the
+// scaling constant K of the win probability sigmoid and then adjusts every
+// parameter but the fixed ones one step at a time as long as the prediction
+// error drops.
+//
+// Usage: tune <positions> [--params <start.json>] [--output <tuned.json>]
+//             [--iterations <n>] [--step <n>] [--threads <n>]
+use rust_chess_engine::eval::{evaluate, piece_value};
+use rust_chess_engine::params::{self, EvalParams};
+use rust_chess_engine::{is_in_check, Board, Color, Piece, PieceType, Square};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::thread;
+use std::time::Instant;
+
+// Captures are followed at most this deep when resolving a position
+const MAX_QUIESCENCE_PLY: i32 = 8;
+
+struct Options {
+    positions: String,
+    start: Option<String>,
+    output: String,
+    iterations: usize,
+    step: i64,
+    threads: usize,
+}
+
+// A quiet position and the result of its game from White's point of view:
+// 1.0 win, 0.5 draw, 0.0 loss
+struct Sample {
+    board: Board,
+    side_to_move: Color,
+    result: f64,
+}
+
+fn parse_args() -> Option<Options> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options {
+        positions: String::new(),
+        start: None,
+        output: "tuned.json".to_string(),
+        iterations: 100,
+        step: 1,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match (args[i].as_str(), value) {
+            ("--params", Some(v)) => options.start = Some(v.clone()),
+            ("--output", Some(v)) => options.output = v.clone(),
+            ("--iterations", Some(v)) => options.iterations = v.parse().ok()?,
+            ("--step", Some(v)) => options.step = v.parse().ok().filter(|&s| s > 0)?,
+            ("--threads", Some(v)) => options.threads = v.parse().ok().filter(|&n| n > 0)?,
+            (arg, _) if !arg.starts_with("--") && options.positions.is_empty() => {
+                options.positions = arg.to_string();
+                i += 1;
+                continue;
+            }
+            _ => return None,
+        }
+        i += 2;
+    }
+    if options.positions.is_empty() { None } else { Some(options) }
+}
+
+// The result can be given PGN style ("1-0", "0-1", "1/2-1/2", as in EPD
+// c9 operations) or as a number ("[1.0]", "[0.5]", "[0.0]") anywhere after
+// the FEN
+fn parse_result(line: &str) -> Option<f64> {
+    line.split(|c: char| c.is_whitespace() || matches!(c, ';' | '"' | '[' | ']' | ','))
+        .rev()
+        .find_map(|token| match token {
+            "1-0" | "1.0" => Some(1.0),
+            "0-1" | "0.0" => Some(0.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            _ => None,
+        })
+}
+
+fn read_positions(path: &str) -> io::Result<Vec<(Board, Color, f64)>> {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match (Board::from_fen(&line), parse_result(&line)) {
+            (Some((board, color)), Some(result)) => positions.push((board, color, result)),
+            _ => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        println!("Skipped {} lines without a position or a result", skipped);
+    }
+    Ok(positions)
+}
+
+fn captures(board: &Board, color: Color) -> Vec<(Square, Square)> {
+    let mut moves = Vec::new();
+    for from in 0u8..128 {
+        if let Some(piece) = board.get_piece(from)
+            && piece.color == color
+        {
+            for to in board.generate_legal_moves_for_piece(from) {
+                // A pawn moving diagonally to an empty square takes en passant
+                let en_passant = piece.kind == PieceType::Pawn
+                    && from & 7 != to & 7
+                    && board.en_passant == Some(to);
+                let victim = board.get_piece(to).or_else(|| {
+                    en_passant.then_some(Piece { color: color.opposite(), kind: PieceType::Pawn })
+                });
+                if let Some(victim) = victim {
+                    let order = piece_value(&params::get(), victim.kind).mg * 8 - piece.kind as i32;
+                    moves.push((order, (from, to)));
+                }
+            }
+        }
+    }
+    moves.sort_by_key(|&(order, _)| std::cmp::Reverse(order));
+    moves.into_iter().map(|(_, mv)| mv).collect()
+}
+
+// Capture-only search; returns the score and the quiet position at the end
+// of the principal variation
+fn quiescence(board: &Board, color: Color, mut alpha: i32, beta: i32, ply: i32) -> (i32, Board, Color) {
+    let stand_pat = evaluate(board, color);
+    let mut best = (stand_pat, board.clone(), color);
+    if stand_pat >= beta || ply >= MAX_QUIESCENCE_PLY {
+        return best;
+    }
+    alpha = alpha.max(stand_pat);
+
+    for (from, to) in captures(board, color) {
+        let mut child = board.clone();
+        child.make_move(from, to);
+        let (score, leaf, leaf_color) = quiescence(&child, color.opposite(), -beta, -alpha, ply + 1);
+        let score = -score;
+        if score > best.0 {
+            best = (score, leaf, leaf_color);
+        }
+        if score > alpha {
+            alpha = score;
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    best
+}
+
+fn resolve(positions: Vec<(Board, Color, f64)>) -> Vec<Sample> {
+    positions
+        .into_iter()
+        .map(|(board, color, result)| {
+            // Captures are not forced while in check, so leave those alone
+            let (board, side_to_move) = if is_in_check(&board, color) {
+                (board, color)
+            } else {
+                let (_, leaf, leaf_color) = quiescence(&board, color, -i32::MAX, i32::MAX, 0);
+                (leaf, leaf_color)
+            };
+            Sample { board, side_to_move, result }
+        })
+        .collect()
+}
+
+fn win_probability(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0))
+}
+
+// Mean squared difference between the results and the predicted win
+// probabilities, evaluated with the current global parameters
+fn error(samples: &[Sample], k: f64, threads: usize) -> f64 {
+    let chunk = samples.len().div_ceil(threads).max(1);
+    let sum: f64 = thread::scope(|s| {
+        let handles: Vec<_> = samples
+            .chunks(chunk)
+            .map(|part| {
+                s.spawn(move || {
+                    part.iter()
+                        .map(|sample| {
+                            let score = evaluate(&sample.board, sample.side_to_move);
+                            let white = if sample.side_to_move == Color::White { score } else { -score };
+                            (sample.result - win_probability(white, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    sum / samples.len() as f64
+}
+
+// Golden section search; the error is unimodal in K
+fn optimize_k(samples: &[Sample], threads: usize) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut lo, mut hi) = (0.0f64, 4.0f64);
+    let mut a = hi - ratio * (hi - lo);
+    let mut b = lo + ratio * (hi - lo);
+    let mut error_a = error(samples, a, threads);
+    let mut error_b = error(samples, b, threads);
+    while hi - lo > 1e-4 {
+        if error_a < error_b {
+            hi = b;
+            b = a;
+            error_b = error_a;
+            a = hi - ratio * (hi - lo);
+            error_a = error(samples, a, threads);
+        } else {
+            lo = a;
+            a = b;
+            error_a = error_b;
+            b = lo + ratio * (hi - lo);
+            error_b = error(samples, b, threads);
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+// Parameters left out of tuning, by their path in the JSON file: the king's
+// value is the same for both sides, pawns and kings have no mobility term
+// and no weight as king attackers, and pawns never stand on the first or
+// last rank of their piece-square tables
+const FIXED: [&str; 12] = [
+    "/piece_values_mg/5",
+    "/piece_values_eg/5",
+    "/mobility_weights/0",
+    "/mobility_weights/5",
+    "/mobility_baseline/0",
+    "/mobility_baseline/5",
+    "/king_attack_weights/0",
+    "/king_attack_weights/5",
+    "/pst_mg/0/0",
+    "/pst_mg/0/7",
+    "/pst_eg/0/0",
+    "/pst_eg/0/7",
+];
+
+fn is_fixed(path: &str) -> bool {
+    FIXED.iter().any(|fixed| path == *fixed || path.starts_with(&format!("{}/", fixed)))
+}
+
+// Every number in the parameter set that is tuned, in a fixed order
+fn flatten(value: &Value, path: &str, out: &mut Vec<i64>) {
+    if is_fixed(path) {
+        return;
+    }
+    match value {
+        Value::Number(n) => out.push(n.as_i64().unwrap_or(0)),
+        Value::Array(items) => {
+            items.iter().enumerate().for_each(|(i, item)| flatten(item, &format!("{}/{}", path, i), out))
+        }
+        Value::Object(fields) => {
+            fields.iter().for_each(|(name, item)| flatten(item, &format!("{}/{}", path, name), out))
+        }
+        _ => {}
+    }
+}
+
+fn unflatten(value: &mut Value, path: &str, numbers: &mut impl Iterator<Item = i64>) {
+    if is_fixed(path) {
+        return;
+    }
+    match value {
+        Value::Number(n) => *n = numbers.next().unwrap_or(0).into(),
+        Value::Array(items) => items
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, item)| unflatten(item, &format!("{}/{}", path, i), numbers)),
+        Value::Object(fields) => {
+            fields.iter_mut().for_each(|(name, item)| unflatten(item, &format!("{}/{}", path, name), numbers))
+        }
+        _ => {}
+    }
+}
+
+fn apply(template: &Value, numbers: &[i64]) -> EvalParams {
+    let mut value = template.clone();
+    unflatten(&mut value, "", &mut numbers.iter().copied());
+    let params: EvalParams = serde_json::from_value(value).expect("parameter layout is fixed");
+    params::set(params.clone());
+    params
+}
+
+fn main() {
+    let Some(options) = parse_args() else {
+        eprintln!(
+            "Usage: tune <positions> [--params <start.json>] [--output <tuned.json>] \
+             [--iterations <n>] [--step <n>] [--threads <n>]"
+        );
+        return;
+    };
+
+    if let Some(path) = &options.start {
+        match EvalParams::load(path) {
+            Ok(p) => params::set(p),
+            Err(e) => {
+                eprintln!("Cannot load evaluation parameters from {}: {}", path, e);
+                return;
+            }
+        }
+    }
+
+    let positions = match read_positions(&options.positions) {
+        Ok(positions) if !positions.is_empty() => positions,
+        Ok(_) => {
+            eprintln!("No labelled positions in {}", options.positions);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", options.positions, e);
+            return;
+        }
+    };
+    let start = Instant::now();
+    let samples = resolve(positions);
+    println!("Resolved {} positions in {:.1}s", samples.len(), start.elapsed().as_secs_f64());
+
+    let threads = options.threads;
+    let k = optimize_k(&samples, threads);
+    let mut best_error = error(&samples, k, threads);
+    println!("K = {:.4}, error {:.6}", k, best_error);
+
+    let template = serde_json::to_value(&*params::get()).expect("parameters serialize");
+    let mut numbers = Vec::new();
+    flatten(&template, "", &mut numbers);
+    println!("Tuning {} parameters", numbers.len());
+
+    for iteration in 1..=options.iterations {
+        let mut improved = 0;
+        for i in 0..numbers.len() {
+            for delta in [options.step, -options.step] {
+                numbers[i] += delta;
+                apply(&template, &numbers);
+                let e = error(&samples, k, threads);
+                if e < best_error {
+                    best_error = e;
+                    improved += 1;
+                    break;
+                }
+                numbers[i] -= delta;
+            }
+        }
+
+        // Leave the global parameters at the best values found so far
+        let params = apply(&template, &numbers);
+        if let Err(e) = params.save(&options.output) {
+            eprintln!("Cannot save parameters to {}: {}", options.output, e);
+            return;
+        }
+        println!(
+            "Iteration {}: error {:.6}, {} parameters changed, saved to {} ({:.0}s)",
+            iteration,
+            best_error,
+            improved,
+            options.output,
+            start.elapsed().as_secs_f64()
+        );
+        if improved == 0 {
+            break;
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// src/bitbase.rs
+//
+// KPK bitbase: whether king and pawn against king is a win, for every
+// position, generated by retrograde analysis the first time it is needed.
+// Positions are normalised so that the pawn is White's and stands on the
+// a-d files. Squares in this module are numbered a1 = 0 .. h8 = 63.
+use crate::pawns::relative_rank;
+use crate::{Board, Color, Piece, PieceType, Square};
+use lazy_static::lazy_static;
+
+// White king, black king, side to move, pawn file (a-d) and pawn rank (2-7)
+const POSITIONS: usize = 64 * 64 * 2 * 4 * 6;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+lazy_static! {
+    // One bit per position, set when White wins
+    static ref KPK: Vec<u64> = generate();
+}
+
+fn index(side_to_move: Color, black_king: usize, white_king: usize, pawn: usize) -> usize {
+    white_king | black_king << 6 | (side_to_move as usize) << 12 | (pawn & 7) << 13 | (6 - pawn / 8) << 15
+}
+
+fn file(sq: usize) -> i32 {
+    (sq % 8) as i32
+}
+
+fn rank(sq: usize) -> i32 {
+    (sq / 8) as i32
+}
+
+fn distance(a: usize, b: usize) -> i32 {
+    (file(a) - file(b)).abs().max((rank(a) - rank(b)).abs())
+}
+
+fn king_moves(sq: usize) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |&to| distance(sq, to) == 1)
+}
+
+fn pawn_attacks(pawn: usize, sq: usize) -> bool {
+    rank(sq) == rank(pawn) + 1 && (file(sq) - file(pawn)).abs() == 1
+}
+
+// Result of a position that follows from the rules alone, before looking at
+// the positions it leads to
+fn classify_leaf(side_to_move: Color, black_king: usize, white_king: usize, pawn: usize) -> u8 {
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (side_to_move == Color::White && pawn_attacks(pawn, black_king))
+    {
+        return INVALID;
+    }
+    let promotion = pawn + 8;
+    if side_to_move == Color::White {
+        // The pawn promotes and the queen cannot be taken
+        if rank(pawn) == 6
+            && white_king != promotion
+            && black_king != promotion
+            && (distance(black_king, promotion) > 1 || distance(white_king, promotion) == 1)
+        {
+            return WIN;
+        }
+    } else {
+        let attacked = |sq: usize| distance(white_king, sq) <= 1 || pawn_attacks(pawn, sq);
+        // Stalemate, or the pawn falls
+        if king_moves(black_king).all(attacked)
+            || (distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1)
+        {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+// White to move wins if one move wins and draws if all moves draw; Black to
+// move draws if one move draws and loses if all moves lose. Positions that
+// cannot be reached (INVALID) add nothing.
+fn classify(db: &[u8], side_to_move: Color, black_king: usize, white_king: usize, pawn: usize) -> u8 {
+    let mut results = INVALID;
+    if side_to_move == Color::White {
+        for to in king_moves(white_king) {
+            results |= db[index(Color::Black, black_king, to, pawn)];
+        }
+        // Squares with a king on them are invalid, so pushes need no more checks
+        if rank(pawn) < 6 {
+            results |= db[index(Color::Black, black_king, white_king, pawn + 8)];
+        }
+        if rank(pawn) == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+            results |= db[index(Color::Black, black_king, white_king, pawn + 16)];
+        }
+        if results & WIN != 0 {
+            WIN
+        } else if results & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for to in king_moves(black_king) {
+            results |= db[index(Color::White, to, white_king, pawn)];
+        }
+        if results & DRAW != 0 {
+            DRAW
+        } else if results & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut db = vec![INVALID; POSITIONS];
+    let mut positions = Vec::with_capacity(POSITIONS);
+    for side_to_move in [Color::White, Color::Black] {
+        for black_king in 0..64 {
+            for white_king in 0..64 {
+                for pawn_rank in 1..7 {
+                    for pawn_file in 0..4 {
+                        let pawn = pawn_rank * 8 + pawn_file;
+                        let idx = index(side_to_move, black_king, white_king, pawn);
+                        db[idx] = classify_leaf(side_to_move, black_king, white_king, pawn);
+                        positions.push((idx, side_to_move, black_king, white_king, pawn));
+                    }
+                }
+            }
+        }
+    }
+
+    // Propagate known results until nothing changes; what is still unknown
+    // then is a draw
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(idx, side_to_move, black_king, white_king, pawn) in &positions {
+            if db[idx] == UNKNOWN {
+                db[idx] = classify(&db, side_to_move, black_king, white_king, pawn);
+                changed |= db[idx] != UNKNOWN;
+            }
+        }
+    }
+
+    let mut bits = vec![0u64; POSITIONS / 64];
+    for (idx, &result) in db.iter().enumerate() {
+        if result == WIN {
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+    bits
+}
+
+// Builds the bitbase now rather than during the first search that needs it
+pub fn init() {
+    lazy_static::initialize(&KPK);
+}
+
+// Whether White, with the pawn, wins
+fn probe(side_to_move: Color, white_king: usize, pawn: usize, black_king: usize) -> bool {
+    let idx = index(side_to_move, black_king, white_king, pawn);
+    KPK[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+// 0x88 square to a1 = 0 .. h8 = 63, seen from `color`'s side of the board
+fn normalise(sq: Square, color: Color) -> usize {
+    relative_rank(sq, color) as usize * 8 + (sq & 7) as usize
+}
+
+// For a KPK position: Some(true) if the side with the pawn wins, Some(false)
+// if it is a draw; None for any other material, or a pawn on its last rank
+pub fn probe_kpk(board: &Board, side_to_move: Color) -> Option<bool> {
+    let mut kings = [None; 2];
+    let mut pawn = None;
+    let mut pieces = 0;
+    for sq in 0u8..128 {
+        let Some(piece) = board.get_piece(sq) else { continue };
+        pieces += 1;
+        match piece {
+            Piece { kind: PieceType::King, color } => kings[color as usize] = Some(sq),
+            Piece { kind: PieceType::Pawn, color } if pawn.is_none() => pawn = Some((sq, color)),
+            _ => return None,
+        }
+    }
+    let (pawn, strong) = pawn?;
+    if pieces != 3 || !(1..7).contains(&relative_rank(pawn, strong)) {
+        return None;
+    }
+    let strong_king = kings[strong as usize]?;
+    let weak_king = kings[strong.opposite() as usize]?;
+
+    let mut squares = [strong_king, pawn, weak_king].map(|sq| normalise(sq, strong));
+    if file(squares[1]) > 3 {
+        squares = squares.map(|sq| sq ^ 7);
+    }
+    let side_to_move = if side_to_move == strong { Color::White } else { Color::Black };
+    Some(probe(side_to_move, squares[0], squares[1], squares[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kpk(fen: &str) -> Option<bool> {
+        let (board, color) = Board::from_fen(fen).unwrap();
+        probe_kpk(&board, color)
+    }
+
+    #[test]
+    fn king_on_the_sixth_in_front_of_the_pawn_wins() {
+        assert_eq!(kpk("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(true));
+        assert_eq!(kpk("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(true));
+    }
+
+    #[test]
+    fn the_side_to_move_decides() {
+        assert_eq!(kpk("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"), Some(true));
+        // Stalemate
+        assert_eq!(kpk("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(false));
+    }
+
+    #[test]
+    fn black_pawns_are_seen_from_black() {
+        assert_eq!(kpk("8/8/8/8/8/4k3/4p3/4K3 b - - 0 1"), Some(true));
+        assert_eq!(kpk("8/8/8/8/8/4k3/4p3/4K3 w - - 0 1"), Some(false));
+    }
+
+    #[test]
+    fn known_draws() {
+        // Rook pawn with the defending king in the corner
+        assert_eq!(kpk("k7/8/8/8/8/8/P7/K7 w - - 0 1"), Some(false));
+        // The pawn falls
+        assert_eq!(kpk("8/8/8/8/8/6k1/7P/K7 b - - 0 1"), Some(false));
+    }
+
+    #[test]
+    fn a_pawn_outside_the_kings_square_runs_home() {
+        assert_eq!(kpk("7k/8/8/8/8/8/P7/K7 w - - 0 1"), Some(true));
+    }
+
+    #[test]
+    fn other_material_is_not_probed() {
+        assert_eq!(kpk("4k3/8/4K3/4P3/8/8/8/7R w - - 0 1"), None);
+        assert_eq!(kpk("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), None);
+    }
+}
@@ -0,0 +1,105 @@
+// src/book.rs
+//
+// Polyglot opening books (.bin). A book is a list of 16-byte entries sorted
+// by key, all fields big-endian: the position's key (8 bytes, see
+// src/polyglot.rs), a move (2), its weight (2) and a learning value (4)
+// that is not used. Several entries with the same key are the book's moves
+// for that position.
+use crate::polyglot;
+use crate::{Board, Color, Square};
+use rand::Rng;
+use std::io;
+
+pub const ENTRY_SIZE: usize = 16;
+
+#[derive(Copy, Clone)]
+pub struct Entry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl Entry {
+    fn from_bytes(bytes: &[u8]) -> Entry {
+        Entry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn load(path: &str) -> io::Result<Book> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a Polyglot book"));
+        }
+        let mut entries: Vec<Entry> = bytes.chunks_exact(ENTRY_SIZE).map(Entry::from_bytes).collect();
+        // Lookups rely on the order; moves of one position stay as listed
+        entries.sort_by_key(|e| e.key);
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self, key: u64) -> &[Entry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = start + self.entries[start..].partition_point(|e| e.key == key);
+        &self.entries[start..end]
+    }
+
+    // The book's moves for a position with their weights, leaving out moves
+    // the engine cannot play
+    pub fn moves(&self, board: &Board, color: Color) -> Vec<((Square, Square), u16)> {
+        self.entries(polyglot::key(board, color))
+            .iter()
+            .filter_map(|e| Some((polyglot::decode_move(board, color, e.mv)?, e.weight)))
+            .collect()
+    }
+
+    // A move to play: the heaviest one with `best_move_only`, otherwise a
+    // random one with a chance proportional to its weight. Moves with weight
+    // 0 are never played.
+    pub fn pick(&self, board: &Board, color: Color, best_move_only: bool) -> Option<(Square, Square)> {
+        let moves: Vec<_> = self.moves(board, color).into_iter().filter(|&(_, w)| w > 0).collect();
+        if best_move_only {
+            // The first of equally heavy moves, as the book lists them
+            let best = moves.iter().map(|&(_, w)| w).max()?;
+            return moves.iter().find(|&&(_, w)| w == best).map(|&(mv, _)| mv);
+        }
+        let total: u32 = moves.iter().map(|&(_, w)| w as u32).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut choice = rand::thread_rng().gen_range(0..total);
+        for (mv, weight) in moves {
+            if choice < weight as u32 {
+                return Some(mv);
+            }
+            choice -= weight as u32;
+        }
+        None
+    }
+}
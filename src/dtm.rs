@@ -0,0 +1,858 @@
+// src/dtm.rs
+//
+// Distance-to-mate tablebases for endgames of up to four pieces, generated
+// by the engine itself with retrograde analysis (the gentb binary) and
+// probed by the search.
+//
+// A table covers one material configuration and is stored in a file named
+// after it, stronger side first: "KRvK.dtm", "KQvKR.dtm", "KPvKP.dtm". The
+// file layout, all numbers little-endian:
+//
+//   bytes 0-3    magic "RDTM"
+//   byte  4      format version, 1
+//   byte  5      number of pieces n, kings included
+//   n bytes      the pieces in index order, side << 3 | kind: side 0 is the
+//                first side of the name, kind 0 pawn, 1 knight, 2 bishop,
+//                3 rook, 4 queen, 5 king
+//   8 bytes      number of positions
+//   then         one byte per position: 0 for a draw (and for positions
+//                that cannot occur), odd m when the side to move mates in m
+//                plies, even m when the side to move is mated in m - 2 plies
+//
+// Positions are indexed after making the first side White (flipping the
+// board if needed) and mirroring it so that White's king stands on files
+// a-d and, in tables without pawns, on ranks 1-4. With squares numbered
+// a1 = 0 .. h8 = 63 and the pieces in the order of the header:
+//
+//   index = ((king * 64 + sq[1]) * 64 + ... + sq[n-1]) * 2 + side to move
+//
+// where king = rank * 4 + file of White's king and side to move is 0 for
+// White. Identical pieces are listed in increasing square order.
+//
+// The generator works on a list of at most four pieces on the 0x88 board
+// rather than on `Board`, because it visits every position many times.
+// Promotions are played as in chess, to any piece, although the engine
+// itself only promotes to a queen; positions with a pawn on the last rank
+// are not in the tables, and neither is castling or en passant.
+use crate::tablebase;
+use crate::{Board, Color, Piece, PieceType, Square, BISHOP_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS, ROOK_DIRECTIONS};
+use lazy_static::lazy_static;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+// Score of a position won in 0 plies; above Syzygy and evaluation wins
+pub const DTM_WIN: i32 = 30000;
+pub const MAX_PIECES: usize = 4;
+
+const MAGIC: [u8; 4] = *b"RDTM";
+const VERSION: u8 = 1;
+// Longest distance a position byte can hold
+pub const MAX_PLIES: usize = 253;
+
+// Result for the side to move, in plies to mate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dtm {
+    Win(u32),
+    Draw,
+    Loss(u32),
+}
+
+impl Dtm {
+    fn from_byte(byte: u8) -> Dtm {
+        match byte {
+            0 => Dtm::Draw,
+            m if m % 2 == 1 => Dtm::Win(m as u32),
+            m => Dtm::Loss(m as u32 - 2),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Dtm::Win(plies) => plies as u8,
+            Dtm::Draw => 0,
+            Dtm::Loss(plies) => plies as u8 + 2,
+        }
+    }
+
+    // Search score `ply` plies from the root: faster mates score higher,
+    // slower losses less badly
+    pub fn score(self, ply: i32) -> i32 {
+        match self {
+            Dtm::Win(plies) => DTM_WIN - ply - plies as i32,
+            Dtm::Draw => 0,
+            Dtm::Loss(plies) => -DTM_WIN + ply + plies as i32,
+        }
+    }
+}
+
+const KIND_LETTERS: [(PieceType, char); 6] = [
+    (PieceType::King, 'K'),
+    (PieceType::Queen, 'Q'),
+    (PieceType::Rook, 'R'),
+    (PieceType::Bishop, 'B'),
+    (PieceType::Knight, 'N'),
+    (PieceType::Pawn, 'P'),
+];
+
+// Position of a kind in table order, king first and pawn last
+fn kind_order(kind: PieceType) -> usize {
+    KIND_LETTERS.iter().position(|&(k, _)| k == kind).unwrap()
+}
+
+fn kind_code(kind: PieceType) -> u8 {
+    match kind {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    }
+}
+
+fn kind_value(kind: PieceType) -> i32 {
+    match kind {
+        PieceType::Pawn => 1,
+        PieceType::Knight | PieceType::Bishop => 3,
+        PieceType::Rook => 5,
+        PieceType::Queen => 9,
+        PieceType::King => 0,
+    }
+}
+
+// One side's pieces: stronger material first, then more pieces, then the
+// better piece types
+fn strength(kinds: &[PieceType]) -> (i32, usize, Vec<usize>) {
+    let value = kinds.iter().map(|&k| kind_value(k)).sum();
+    (value, kinds.len(), kinds.iter().map(|&k| 5 - kind_order(k)).collect())
+}
+
+// The pieces of a table in index order, the first side White
+#[derive(Clone, PartialEq)]
+pub struct Material {
+    pieces: Vec<Piece>,
+}
+
+impl Material {
+    // Orders the pieces of a position; true if the sides had to be swapped
+    // to put the stronger one first
+    fn from_pieces(pieces: &[Piece]) -> (Material, bool) {
+        let side = |color: Color| {
+            let mut kinds: Vec<PieceType> = pieces.iter().filter(|p| p.color == color).map(|p| p.kind).collect();
+            kinds.sort_by_key(|&k| kind_order(k));
+            kinds
+        };
+        let (white, black) = (side(Color::White), side(Color::Black));
+        let swapped = strength(&black) > strength(&white);
+        let (first, second) = if swapped { (black, white) } else { (white, black) };
+        let pieces = first
+            .into_iter()
+            .map(|kind| Piece { color: Color::White, kind })
+            .chain(second.into_iter().map(|kind| Piece { color: Color::Black, kind }))
+            .collect();
+        (Material { pieces }, swapped)
+    }
+
+    // "KRvK"; the sides can be given in either order
+    pub fn parse(name: &str) -> Option<Material> {
+        let (first, second) = name.split_once('v')?;
+        let mut pieces = Vec::new();
+        for (side, color) in [(first, Color::White), (second, Color::Black)] {
+            if !side.starts_with('K') || side[1..].contains('K') {
+                return None;
+            }
+            for c in side.chars() {
+                let &(kind, _) = KIND_LETTERS.iter().find(|&&(_, letter)| letter == c)?;
+                pieces.push(Piece { color, kind });
+            }
+        }
+        let material = Material::from_pieces(&pieces).0;
+        (3..=MAX_PIECES).contains(&material.pieces.len()).then_some(material)
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if i > 0 && piece.kind == PieceType::King {
+                name.push('v');
+            }
+            name.push(KIND_LETTERS[kind_order(piece.kind)].1);
+        }
+        name
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.iter().any(|p| p.kind == PieceType::Pawn)
+    }
+
+    // Squares White's king is mirrored onto
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() { 32 } else { 16 }
+    }
+
+    pub fn positions(&self) -> usize {
+        self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1) * 2
+    }
+
+    // The tables positions in this one can reach by a capture or a
+    // promotion, except the bare kings
+    pub fn successors(&self) -> Vec<Material> {
+        let mut successors: Vec<Material> = Vec::new();
+        let mut add = |pieces: Vec<Piece>| {
+            let material = Material::from_pieces(&pieces).0;
+            if material.pieces.len() > 2 && !successors.contains(&material) {
+                successors.push(material);
+            }
+        };
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if piece.kind == PieceType::King {
+                continue;
+            }
+            let mut captured = self.pieces.clone();
+            captured.remove(i);
+            add(captured);
+            if piece.kind == PieceType::Pawn {
+                for kind in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                    let mut promoted = self.pieces.clone();
+                    promoted[i].kind = kind;
+                    add(promoted);
+                }
+            }
+        }
+        successors
+    }
+
+    // Position with this index, or None if it cannot occur or another index
+    // stands for it
+    fn position(&self, index: usize) -> Option<Position> {
+        let mut pos = Position {
+            pieces: [Piece { color: Color::White, kind: PieceType::King }; MAX_PIECES],
+            squares: [0; MAX_PIECES],
+            len: self.pieces.len(),
+            side_to_move: if index.is_multiple_of(2) { Color::White } else { Color::Black },
+        };
+        let mut rest = index / 2;
+        for i in (1..pos.len).rev() {
+            pos.squares[i] = square_from_index(rest % 64);
+            rest /= 64;
+        }
+        pos.squares[0] = square_from_index(rest / 4 * 8 + rest % 4);
+        pos.pieces[..pos.len].copy_from_slice(&self.pieces);
+
+        let legal = (0..pos.len).all(|i| {
+            let pawn_row = pos.pieces[i].kind == PieceType::Pawn && matches!(pos.squares[i] >> 4, 0 | 7);
+            !pawn_row && pos.occupant(pos.squares[i]) == Some(i)
+        });
+        (legal && !pos.in_check(pos.side_to_move.opposite()) && self.index(&pos) == index).then_some(pos)
+    }
+
+    // Index of a position with this material, White being the first side
+    fn index(&self, pos: &Position) -> usize {
+        let mut squares = [0; MAX_PIECES];
+        squares[..pos.len].copy_from_slice(&pos.squares[..pos.len]);
+        let king = squares[..pos.len]
+            .iter()
+            .zip(&pos.pieces)
+            .find(|(_, p)| p.kind == PieceType::King && p.color == Color::White)
+            .map(|(&sq, _)| sq)
+            .unwrap();
+        let mut mirror = 0;
+        if king & 7 > 3 {
+            mirror ^= 7;
+        }
+        if !self.has_pawns() && king >> 4 < 4 {
+            mirror ^= 0x70;
+        }
+
+        // (slot, square) pairs, sorted into index order
+        let mut order = [(0, 0); MAX_PIECES];
+        for i in 0..pos.len {
+            let piece = pos.pieces[i];
+            order[i] = ((piece.color as usize) * 8 + kind_order(piece.kind), square_index(squares[i] ^ mirror));
+        }
+        let order = &mut order[..pos.len];
+        order.sort_unstable();
+
+        let king = order[0].1;
+        let mut index = king / 8 * 4 + king % 8;
+        for &(_, sq) in &order[1..] {
+            index = index * 64 + sq;
+        }
+        index * 2 + pos.side_to_move as usize
+    }
+}
+
+// 0x88 square to a1 = 0 .. h8 = 63 and back
+fn square_index(sq: Square) -> usize {
+    (7 - (sq >> 4) as usize) * 8 + (sq & 7) as usize
+}
+
+fn square_from_index(idx: usize) -> Square {
+    ((7 - idx / 8) * 16 + idx % 8) as Square
+}
+
+fn step(sq: Square, offset: i8) -> Option<Square> {
+    let to = sq as i16 + offset as i16;
+    (0..128).contains(&to).then_some(to as Square).filter(|&to| Board::is_valid(to))
+}
+
+fn directions(kind: PieceType) -> (&'static [i8], bool) {
+    match kind {
+        PieceType::Knight => (&KNIGHT_OFFSETS, false),
+        PieceType::Bishop => (&BISHOP_DIRECTIONS, true),
+        PieceType::Rook => (&ROOK_DIRECTIONS, true),
+        PieceType::Queen => (&KING_OFFSETS, true),
+        PieceType::King | PieceType::Pawn => (&KING_OFFSETS, false),
+    }
+}
+
+fn pawn_direction(color: Color) -> i8 {
+    if color == Color::White { -16 } else { 16 }
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    pieces: [Piece; MAX_PIECES],
+    squares: [Square; MAX_PIECES],
+    len: usize,
+    side_to_move: Color,
+}
+
+// A legal move as the position it leads to; captures and promotions leave
+// the table
+struct Child {
+    pos: Position,
+    exits: bool,
+}
+
+impl Position {
+    fn from_board(board: &Board, color: Color) -> Option<Position> {
+        let mut pos = Position {
+            pieces: [Piece { color: Color::White, kind: PieceType::King }; MAX_PIECES],
+            squares: [0; MAX_PIECES],
+            len: 0,
+            side_to_move: color,
+        };
+        for sq in 0u8..128 {
+            let Some(piece) = board.get_piece(sq) else { continue };
+            if pos.len == MAX_PIECES || (piece.kind == PieceType::Pawn && matches!(sq >> 4, 0 | 7)) {
+                return None;
+            }
+            pos.pieces[pos.len] = piece;
+            pos.squares[pos.len] = sq;
+            pos.len += 1;
+        }
+        Some(pos)
+    }
+
+    fn occupant(&self, sq: Square) -> Option<usize> {
+        (0..self.len).find(|&i| self.squares[i] == sq)
+    }
+
+    fn attacks(&self, i: usize, target: Square) -> bool {
+        let (from, piece) = (self.squares[i], self.pieces[i]);
+        if piece.kind == PieceType::Pawn {
+            let forward = pawn_direction(piece.color);
+            return [forward - 1, forward + 1].iter().any(|&d| step(from, d) == Some(target));
+        }
+        let (offsets, slides) = directions(piece.kind);
+        for &d in offsets {
+            let mut sq = from;
+            while let Some(to) = step(sq, d) {
+                if to == target {
+                    return true;
+                }
+                if !slides || self.occupant(to).is_some() {
+                    break;
+                }
+                sq = to;
+            }
+        }
+        false
+    }
+
+    fn in_check(&self, color: Color) -> bool {
+        let Some(king) = (0..self.len).find(|&i| self.pieces[i] == Piece { color, kind: PieceType::King }) else {
+            return false;
+        };
+        (0..self.len).any(|i| self.pieces[i].color != color && self.attacks(i, self.squares[king]))
+    }
+
+    fn moved(&self, i: usize, to: Square) -> Position {
+        let mut pos = *self;
+        pos.squares[i] = to;
+        pos.side_to_move = self.side_to_move.opposite();
+        pos
+    }
+
+    fn captured(mut self, i: usize) -> Position {
+        self.len -= 1;
+        self.pieces[i] = self.pieces[self.len];
+        self.squares[i] = self.squares[self.len];
+        self
+    }
+
+    fn moves(&self) -> Vec<Child> {
+        let color = self.side_to_move;
+        let mut children = Vec::new();
+        let mut add = |pos: Position, exits: bool| {
+            if !pos.in_check(color) {
+                children.push(Child { pos, exits });
+            }
+        };
+        for i in 0..self.len {
+            let (from, piece) = (self.squares[i], self.pieces[i]);
+            if piece.color != color {
+                continue;
+            }
+            let mut targets = Vec::new();
+            if piece.kind == PieceType::Pawn {
+                let forward = pawn_direction(color);
+                if let Some(one) = step(from, forward).filter(|&sq| self.occupant(sq).is_none()) {
+                    targets.push(one);
+                    let start_row = if color == Color::White { 6 } else { 1 };
+                    if from >> 4 == start_row
+                        && let Some(two) = step(one, forward).filter(|&sq| self.occupant(sq).is_none())
+                    {
+                        targets.push(two);
+                    }
+                }
+                for d in [forward - 1, forward + 1] {
+                    if let Some(to) = step(from, d)
+                        && self.occupant(to).is_some_and(|j| self.pieces[j].color != color)
+                    {
+                        targets.push(to);
+                    }
+                }
+            } else {
+                let (offsets, slides) = directions(piece.kind);
+                for &d in offsets {
+                    let mut sq = from;
+                    while let Some(to) = step(sq, d) {
+                        match self.occupant(to) {
+                            Some(j) if self.pieces[j].color == color => break,
+                            Some(_) => {
+                                targets.push(to);
+                                break;
+                            }
+                            None => targets.push(to),
+                        }
+                        if !slides {
+                            break;
+                        }
+                        sq = to;
+                    }
+                }
+            }
+
+            for to in targets {
+                let mut child = self.moved(i, to);
+                let capture = self.occupant(to);
+                if let Some(j) = capture {
+                    child = child.captured(j);
+                }
+                if piece.kind == PieceType::Pawn && matches!(to >> 4, 0 | 7) {
+                    // The pawn may have moved in the capture's slot
+                    let pawn = child.occupant(to).unwrap();
+                    for kind in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                        let mut promoted = child;
+                        promoted.pieces[pawn].kind = kind;
+                        add(promoted, true);
+                    }
+                } else {
+                    add(child, capture.is_some());
+                }
+            }
+        }
+        children
+    }
+
+    // Positions this one is reached from by a move that stays in the table,
+    // neither a capture nor a promotion
+    fn unmoves(&self) -> Vec<Position> {
+        let color = self.side_to_move.opposite();
+        let mut parents = Vec::new();
+        let empty = |sq: Square| self.occupant(sq).is_none();
+        for i in 0..self.len {
+            let (to, piece) = (self.squares[i], self.pieces[i]);
+            if piece.color != color {
+                continue;
+            }
+            let mut origins = Vec::new();
+            if piece.kind == PieceType::Pawn {
+                let back = -pawn_direction(color);
+                let home_row = if color == Color::White { 7 } else { 0 };
+                if let Some(one) = step(to, back).filter(|&sq| empty(sq) && sq >> 4 != home_row) {
+                    origins.push(one);
+                    let double_row = if color == Color::White { 4 } else { 3 };
+                    if to >> 4 == double_row
+                        && let Some(two) = step(one, back).filter(|&sq| empty(sq))
+                    {
+                        origins.push(two);
+                    }
+                }
+            } else {
+                let (offsets, slides) = directions(piece.kind);
+                for &d in offsets {
+                    let mut sq = to;
+                    while let Some(from) = step(sq, d).filter(|&sq| empty(sq)) {
+                        origins.push(from);
+                        if !slides {
+                            break;
+                        }
+                        sq = from;
+                    }
+                }
+            }
+            for from in origins {
+                let parent = self.moved(i, from);
+                if !parent.in_check(self.side_to_move) {
+                    parents.push(parent);
+                }
+            }
+        }
+        parents
+    }
+}
+
+// Table and index of a position, in the table's orientation; None for the
+// bare kings
+fn locate(pos: &Position) -> Option<(String, usize)> {
+    if pos.len == 2 {
+        return None;
+    }
+    let (material, swapped) = Material::from_pieces(&pos.pieces[..pos.len]);
+    let mut pos = *pos;
+    if swapped {
+        for i in 0..pos.len {
+            pos.pieces[i].color = pos.pieces[i].color.opposite();
+            pos.squares[i] ^= 0x70;
+        }
+        pos.side_to_move = pos.side_to_move.opposite();
+    }
+    Some((material.name(), material.index(&pos)))
+}
+
+struct Generator<'a> {
+    material: &'a Material,
+    done: &'a HashMap<String, Vec<u8>>,
+    table: Vec<u8>,
+    resolved: Vec<bool>,
+}
+
+impl Generator<'_> {
+    // Result of a child position, None while it is not known yet
+    fn value(&self, child: &Child) -> Option<Dtm> {
+        if !child.exits {
+            let index = self.material.index(&child.pos);
+            return self.resolved[index].then(|| Dtm::from_byte(self.table[index]));
+        }
+        let Some((name, index)) = locate(&child.pos) else { return Some(Dtm::Draw) };
+        let table = self.done.get(&name).unwrap_or_else(|| panic!("{} is needed first", name));
+        Some(Dtm::from_byte(table[index]))
+    }
+
+    // Plies to mate when a move reaches a known lost position
+    fn win_in(&self, children: &[Child]) -> Option<usize> {
+        children
+            .iter()
+            .filter_map(|child| match self.value(child) {
+                Some(Dtm::Loss(plies)) => Some(plies as usize + 1),
+                _ => None,
+            })
+            .min()
+    }
+
+    // Plies to being mated when every move is known to lose
+    fn loss_in(&self, children: &[Child]) -> Option<usize> {
+        let mut longest = 0;
+        for child in children {
+            match self.value(child) {
+                Some(Dtm::Win(plies)) => longest = longest.max(plies as usize + 1),
+                _ => return None,
+            }
+        }
+        (!children.is_empty()).then_some(longest)
+    }
+}
+
+// Builds the table for `material`. `done` must hold every table it leads to
+// by a capture or a promotion (see `Material::successors`).
+//
+// Positions are resolved in order of their distance to mate: mates first,
+// then positions with a move to a lost one (won in one more ply), then
+// positions whose moves all reach won ones (lost in one more ply than the
+// longest of them), and so on. Results that leave the table are known from
+// the start. Whatever is never resolved is a draw.
+pub fn generate(material: &Material, done: &HashMap<String, Vec<u8>>) -> Vec<u8> {
+    let size = material.positions();
+    let mut generator = Generator { material, done, table: vec![0; size], resolved: vec![false; size] };
+    let mut pending: Vec<Vec<usize>> = vec![Vec::new(); MAX_PLIES + 2];
+
+    for index in 0..size {
+        let Some(pos) = material.position(index) else { continue };
+        let children = pos.moves();
+        if children.is_empty() {
+            if pos.in_check(pos.side_to_move) {
+                pending[0].push(index);
+            } else {
+                generator.resolved[index] = true; // Stalemate
+            }
+            continue;
+        }
+        if let Some(plies) = generator.win_in(&children) {
+            pending[plies].push(index);
+        }
+        if let Some(plies) = generator.loss_in(&children) {
+            pending[plies].push(index);
+        }
+    }
+
+    // Even plies are losses and odd plies wins for the side to move
+    for plies in 0..=MAX_PLIES {
+        for index in std::mem::take(&mut pending[plies]) {
+            if generator.resolved[index] {
+                continue;
+            }
+            let result = if plies.is_multiple_of(2) { Dtm::Loss(plies as u32) } else { Dtm::Win(plies as u32) };
+            generator.resolved[index] = true;
+            generator.table[index] = result.to_byte();
+
+            let pos = material.position(index).unwrap();
+            for parent in pos.unmoves() {
+                let parent_index = material.index(&parent);
+                if generator.resolved[parent_index] {
+                    continue;
+                }
+                if plies.is_multiple_of(2) {
+                    pending[plies + 1].push(parent_index);
+                } else if let Some(loss) = generator.loss_in(&parent.moves()) {
+                    pending[loss].push(parent_index);
+                }
+            }
+        }
+    }
+    assert!(pending.iter().all(|p| p.is_empty()), "{}: mate too long for the format", material.name());
+    generator.table
+}
+
+fn header(material: &Material) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    header.push(material.pieces.len() as u8);
+    for piece in &material.pieces {
+        header.push((piece.color as u8) << 3 | kind_code(piece.kind));
+    }
+    header.extend_from_slice(&(material.positions() as u64).to_le_bytes());
+    header
+}
+
+// The material of a table file and where its positions start; None if the
+// file is not a complete table
+fn parse_header(bytes: &[u8]) -> Option<(Material, usize)> {
+    if bytes.len() < 6 || bytes[..4] != MAGIC || bytes[4] != VERSION {
+        return None;
+    }
+    let count = bytes[5] as usize;
+    if !(3..=MAX_PIECES).contains(&count) || bytes.len() < 6 + count + 8 {
+        return None;
+    }
+    let mut pieces = Vec::new();
+    for &code in &bytes[6..6 + count] {
+        let color = if code >> 3 == 0 { Color::White } else { Color::Black };
+        let &(kind, _) = KIND_LETTERS.iter().find(|&&(kind, _)| kind_code(kind) == code & 7)?;
+        pieces.push(Piece { color, kind });
+    }
+    let material = Material::from_pieces(&pieces).0;
+    let offset = 6 + count + 8;
+    let positions = u64::from_le_bytes(bytes[6 + count..offset].try_into().ok()?);
+    let complete = material.pieces == pieces
+        && positions == material.positions() as u64
+        && bytes.len() == offset + material.positions();
+    complete.then_some((material, offset))
+}
+
+pub fn write_table(path: &Path, material: &Material, table: &[u8]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    file.write_all(&header(material))?;
+    file.write_all(table)?;
+    file.flush()
+}
+
+pub fn read_table(path: &Path) -> io::Result<(Material, Vec<u8>)> {
+    let bytes = std::fs::read(path)?;
+    let (material, offset) =
+        parse_header(&bytes).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a DTM table"))?;
+    Ok((material, bytes[offset..].to_vec()))
+}
+
+// Every table with 3 to `pieces` pieces, fewer pieces first
+pub fn all_materials(pieces: usize) -> Vec<Material> {
+    let kinds = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight, PieceType::Pawn];
+    // Piece sets of one side, kings left out
+    let mut sets: Vec<Vec<PieceType>> = vec![Vec::new()];
+    for size in 1..=pieces.min(MAX_PIECES).saturating_sub(2) {
+        let smaller: Vec<Vec<PieceType>> = sets.iter().filter(|s| s.len() == size - 1).cloned().collect();
+        for set in smaller {
+            let last = set.last().map_or(0, |&k| kind_order(k) - 1);
+            for &kind in &kinds[last..] {
+                let mut larger = set.clone();
+                larger.push(kind);
+                sets.push(larger);
+            }
+        }
+    }
+
+    let mut materials: Vec<Material> = Vec::new();
+    for first in &sets {
+        for second in &sets {
+            let count = first.len() + second.len() + 2;
+            if count < 3 || count > pieces.min(MAX_PIECES) {
+                continue;
+            }
+            let king = |color| Piece { color, kind: PieceType::King };
+            let all: Vec<Piece> = std::iter::once(king(Color::White))
+                .chain(first.iter().map(|&kind| Piece { color: Color::White, kind }))
+                .chain(std::iter::once(king(Color::Black)))
+                .chain(second.iter().map(|&kind| Piece { color: Color::Black, kind }))
+                .collect();
+            let material = Material::from_pieces(&all).0;
+            if !materials.contains(&material) {
+                materials.push(material);
+            }
+        }
+    }
+    materials.sort_by_key(|m| m.pieces.len());
+    materials
+}
+
+struct Table {
+    map: Mmap,
+    offset: usize,
+}
+
+lazy_static! {
+    static ref TABLES: RwLock<HashMap<String, Table>> = RwLock::new(HashMap::new());
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Loads the .dtm files in the directories of `paths` (see
+// tablebase::directories). Returns the number of tables found.
+pub fn init(paths: &str) -> usize {
+    let mut tables = TABLES.write().unwrap();
+    tables.clear();
+    ACTIVE.store(false, Ordering::SeqCst);
+
+    for dir in tablebase::directories(paths) {
+        let Ok(entries) = std::fs::read_dir(dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "dtm") {
+                continue;
+            }
+            let loaded = tablebase::load(&path, |map| parse_header(&map).map(|(m, offset)| (m, Table { map, offset })));
+            if let Some((material, table)) = loaded {
+                tables.insert(material.name(), table);
+            }
+        }
+    }
+    ACTIVE.store(!tables.is_empty(), Ordering::SeqCst);
+    tables.len()
+}
+
+// Distance to mate for `color`, to move; None without a table for the position
+pub fn probe(board: &Board, color: Color) -> Option<Dtm> {
+    if !ACTIVE.load(Ordering::Relaxed) || board.castling.contains(&true) || board.en_passant.is_some() {
+        return None;
+    }
+    let pos = Position::from_board(board, color)?;
+    let Some((name, index)) = locate(&pos) else { return Some(Dtm::Draw) };
+    let tables = TABLES.read().unwrap();
+    let table = tables.get(&name)?;
+    Some(Dtm::from_byte(table.map[table.offset + index]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    lazy_static! {
+        static ref KQVK: (Material, Vec<u8>) = {
+            let material = Material::parse("KQvK").unwrap();
+            let table = generate(&material, &HashMap::new());
+            (material, table)
+        };
+    }
+
+    fn lookup(fen: &str) -> Dtm {
+        let (board, color) = Board::from_fen(fen).unwrap();
+        let (name, index) = locate(&Position::from_board(&board, color).unwrap()).unwrap();
+        assert_eq!(name, "KQvK");
+        Dtm::from_byte(KQVK.1[index])
+    }
+
+    #[test]
+    fn material_names() {
+        assert_eq!(Material::parse("KvKQ").unwrap().name(), "KQvK");
+        assert_eq!(Material::parse("KRPvKN").map(|m| m.name()), None); // Five pieces
+        assert!(Material::parse("KQ").is_none());
+    }
+
+    #[test]
+    fn kqvk_mate_in_one() {
+        assert_eq!(lookup("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"), Dtm::Win(1));
+        // The same with the colours swapped
+        assert_eq!(lookup("6q1/8/8/8/8/1k6/8/K7 b - - 0 1"), Dtm::Win(1));
+        // And after Qg8#
+        assert_eq!(lookup("k5Q1/8/1K6/8/8/8/8/8 b - - 0 1"), Dtm::Loss(0));
+    }
+
+    #[test]
+    fn kqvk_longest_mate_is_19_plies() {
+        let longest = KQVK.1.iter().map(|&byte| Dtm::from_byte(byte)).filter_map(|dtm| match dtm {
+            Dtm::Win(plies) => Some(plies),
+            _ => None,
+        });
+        assert_eq!(longest.max(), Some(19));
+    }
+
+    #[test]
+    fn stalemate_and_a_hanging_queen_are_draws() {
+        assert_eq!(lookup("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Dtm::Draw);
+        assert_eq!(lookup("k7/1Q6/8/8/8/8/8/7K b - - 0 1"), Dtm::Draw);
+    }
+
+    #[test]
+    fn tables_survive_a_write_and_read() {
+        let (material, table) = &*KQVK;
+        let dir = std::env::temp_dir().join(format!("dtm-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("KQvK.dtm");
+        write_table(&path, material, table).unwrap();
+        let read = read_table(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        let loaded = init(dir.to_str().unwrap());
+        let (board, color) = Board::from_fen("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        let probed = probe(&board, color);
+        init("");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded, 1);
+        assert_eq!(probed, Some(Dtm::Win(1)));
+
+        let (read_material, read_table) = read.unwrap();
+        assert_eq!(read_material.name(), "KQvK");
+        assert!(read_table == *table);
+        let (header_material, offset) = parse_header(&bytes).unwrap();
+        assert_eq!(header_material.name(), "KQvK");
+        assert_eq!(offset, 6 + 3 + 8);
+        // A table cut short is rejected
+        assert!(parse_header(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,281 @@
+// src/endgame.rs
+//
+// Endgames the general evaluation gets wrong, recognised by their material.
+// Some get a specialised evaluation that replaces `evaluate` altogether,
+// others keep the normal evaluation but scale it towards a draw.
+use crate::bitbase;
+use crate::eval::square_colour;
+use crate::pawns::{distance, forward, relative_rank};
+use crate::{Board, Color, Piece, PieceType, Square};
+
+// Scale factors are out of SCALE_NORMAL
+pub const SCALE_NORMAL: i32 = 64;
+// Score of a won endgame before the terms that guide the winning side
+pub const KNOWN_WIN: i32 = 10000;
+
+const ROOK_VALUE_EG: i32 = 530;
+
+pub enum Endgame {
+    // Replaces the evaluation, from the point of view of the side to move
+    Value(i32),
+    // Multiplies the evaluation by factor / SCALE_NORMAL, with one factor per
+    // color that is used when the evaluation favours that color
+    Scale([i32; 2]),
+}
+
+// Piece counts by color and piece type
+struct Material {
+    count: [[u8; 6]; 2],
+}
+
+impl Material {
+    fn new(board: &Board) -> Self {
+        let mut count = [[0; 6]; 2];
+        for sq in 0u8..128 {
+            if let Some(piece) = board.get_piece(sq) {
+                count[piece.color as usize][piece.kind as usize] += 1;
+            }
+        }
+        Material { count }
+    }
+
+    fn get(&self, color: Color, kind: PieceType) -> u8 {
+        self.count[color as usize][kind as usize]
+    }
+
+    // Pieces other than pawns and the king, in pawn units
+    fn non_pawn(&self, color: Color) -> i32 {
+        let c = &self.count[color as usize];
+        3 * (c[1] + c[2]) as i32 + 5 * c[3] as i32 + 9 * c[4] as i32
+    }
+
+    fn total(&self, color: Color) -> i32 {
+        self.non_pawn(color) + self.get(color, PieceType::Pawn) as i32
+    }
+
+    fn bare_king(&self, color: Color) -> bool {
+        self.total(color) == 0
+    }
+
+    // Exactly these pieces besides the king, as (piece type, count) pairs
+    fn is(&self, color: Color, pieces: &[(PieceType, u8)]) -> bool {
+        let mut expected = [0u8; 6];
+        for &(kind, n) in pieces {
+            expected[kind as usize] = n;
+        }
+        self.count[color as usize][..5] == expected[..5]
+    }
+}
+
+fn squares_of(board: &Board, color: Color, kind: PieceType) -> Vec<Square> {
+    (0u8..128)
+        .filter(|&sq| matches!(board.get_piece(sq), Some(Piece { color: c, kind: k }) if c == color && k == kind))
+        .collect()
+}
+
+// 0 in the centre up to 6 in a corner
+fn edge_distance(sq: Square) -> i32 {
+    let file = (sq & 7) as i32;
+    let rank = (sq >> 4) as i32;
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+fn push_to_edge(sq: Square) -> i32 {
+    20 * edge_distance(sq)
+}
+
+fn push_close(a: Square, b: Square) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+// Mate with enough material against a bare king: drive the king to the edge
+// and bring our own king closer
+fn kxk(board: &Board, m: &Material, strong: Color) -> Option<i32> {
+    let strong_king = board.king_square(strong)?;
+    let weak_king = board.king_square(strong.opposite())?;
+    let bishops = squares_of(board, strong, PieceType::Bishop);
+    let bishop_pair = bishops.iter().any(|&b| square_colour(b) != square_colour(bishops[0]));
+    let can_mate = m.get(strong, PieceType::Queen) > 0
+        || m.get(strong, PieceType::Rook) > 0
+        || bishop_pair
+        || (!bishops.is_empty() && m.get(strong, PieceType::Knight) > 0)
+        || m.get(strong, PieceType::Knight) >= 3;
+    if !can_mate {
+        return None;
+    }
+    Some(KNOWN_WIN + 100 * m.total(strong) + push_to_edge(weak_king) + push_close(strong_king, weak_king))
+}
+
+// Bishop and knight: the king can only be mated in a corner of the bishop's colour
+fn kbnk(board: &Board, strong: Color) -> Option<i32> {
+    let strong_king = board.king_square(strong)?;
+    let weak_king = board.king_square(strong.opposite())?;
+    let bishop = *squares_of(board, strong, PieceType::Bishop).first()?;
+    // a8 and h1 are light, a1 and h8 dark
+    let corners: [Square; 2] = if square_colour(bishop) == square_colour(0x00) { [0x00, 0x77] } else { [0x70, 0x07] };
+    let corner_distance = corners.iter().map(|&c| distance(weak_king, c)).min()?;
+    Some(KNOWN_WIN + 200 * (7 - corner_distance) + push_close(strong_king, weak_king))
+}
+
+// King and pawn against king, exact from the bitbase. Wins score more the
+// further the pawn has advanced and the closer the king is to the
+// promotion square, so the king leads the pawn instead of waiting.
+fn kpk(board: &Board, strong: Color, side_to_move: Color) -> Option<i32> {
+    if !bitbase::probe_kpk(board, side_to_move)? {
+        return Some(0);
+    }
+    let strong_king = board.king_square(strong)?;
+    let pawn = *squares_of(board, strong, PieceType::Pawn).first()?;
+    let file = pawn & 7;
+    let queening = if strong == Color::White { file } else { 0x70 | file };
+    Some(KNOWN_WIN + 100 + 20 * relative_rank(pawn, strong) + 10 * (7 - distance(strong_king, queening)))
+}
+
+// Rook against pawn; the rules follow Stockfish
+fn krkp(board: &Board, strong: Color, side_to_move: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = board.king_square(strong)?;
+    let weak_king = board.king_square(weak)?;
+    let rook = *squares_of(board, strong, PieceType::Rook).first()?;
+    let pawn = *squares_of(board, weak, PieceType::Pawn).first()?;
+    let file = pawn & 7;
+    let queening = if weak == Color::White { file } else { 0x70 | file };
+    let in_front = (strong_king & 7) == file && relative_rank(strong_king, weak) > relative_rank(pawn, weak);
+
+    // Our king in front of the pawn, or their king too far from both
+    let value = if in_front
+        || (distance(weak_king, pawn) >= 3 + (side_to_move == weak) as i32 && distance(weak_king, rook) >= 3)
+    {
+        ROOK_VALUE_EG - distance(strong_king, pawn)
+    } else if relative_rank(weak_king, strong) <= 2
+        && distance(weak_king, pawn) == 1
+        && relative_rank(strong_king, strong) >= 3
+        && distance(strong_king, pawn) > 2 + (side_to_move == strong) as i32
+    {
+        80 - 8 * distance(strong_king, pawn)
+    } else {
+        let stop = pawn.wrapping_add(forward(weak) as u8);
+        200 - 8 * (distance(strong_king, stop) - distance(weak_king, stop) - distance(pawn, queening))
+    };
+    Some(value)
+}
+
+// Bishop and rook pawns whose promotion square the bishop does not control:
+// a draw if the defending king gets to the corner
+fn wrong_rook_pawn(board: &Board, m: &Material, strong: Color) -> bool {
+    let weak = strong.opposite();
+    let pawn_count = m.get(strong, PieceType::Pawn);
+    if pawn_count == 0 || !m.is(strong, &[(PieceType::Bishop, 1), (PieceType::Pawn, pawn_count)]) {
+        return false;
+    }
+    let pawns = squares_of(board, strong, PieceType::Pawn);
+    let file = pawns[0] & 7;
+    if (file != 0 && file != 7) || pawns.iter().any(|&p| p & 7 != file) {
+        return false;
+    }
+    let promotion = if strong == Color::White { file } else { 0x70 | file };
+    let bishop = squares_of(board, strong, PieceType::Bishop)[0];
+    let Some(weak_king) = board.king_square(weak) else { return false };
+    square_colour(bishop) != square_colour(promotion) && distance(weak_king, promotion) <= 1
+}
+
+fn opposite_bishops(board: &Board, m: &Material) -> bool {
+    if m.get(Color::White, PieceType::Bishop) != 1 || m.get(Color::Black, PieceType::Bishop) != 1 {
+        return false;
+    }
+    let white = squares_of(board, Color::White, PieceType::Bishop)[0];
+    let black = squares_of(board, Color::Black, PieceType::Bishop)[0];
+    square_colour(white) != square_colour(black)
+}
+
+// The specialised evaluation or scale factor for this position, with a name
+// for the `eval` command
+pub fn probe(board: &Board, side_to_move: Color) -> Option<(&'static str, Endgame)> {
+    let m = Material::new(board);
+    let strong = if m.total(Color::White) >= m.total(Color::Black) { Color::White } else { Color::Black };
+    let weak = strong.opposite();
+    let relative = |value: i32| if side_to_move == strong { value } else { -value };
+
+    if m.bare_king(strong) {
+        return Some(("KK", Endgame::Value(0)));
+    }
+
+    if m.bare_king(weak) {
+        let strong_pawns = m.get(strong, PieceType::Pawn);
+        if strong_pawns == 0 {
+            // KBK, KNK and KNNK cannot force mate
+            if m.non_pawn(strong) <= 3 || m.is(strong, &[(PieceType::Knight, 2)]) {
+                return Some(("insufficient material", Endgame::Value(0)));
+            }
+            if m.is(strong, &[(PieceType::Bishop, 1), (PieceType::Knight, 1)]) {
+                return kbnk(board, strong).map(|v| ("KBNK", Endgame::Value(relative(v))));
+            }
+            if let Some(v) = kxk(board, &m, strong) {
+                return Some(("KXK", Endgame::Value(relative(v))));
+            }
+        }
+        if m.is(strong, &[(PieceType::Pawn, 1)]) {
+            return kpk(board, strong, side_to_move).map(|v| ("KPK", Endgame::Value(relative(v))));
+        }
+        if wrong_rook_pawn(board, &m, strong) {
+            return Some(("wrong rook pawn", Endgame::Value(0)));
+        }
+        if m.non_pawn(strong) >= 5
+            && let Some(v) = kxk(board, &m, strong)
+        {
+            return Some(("KXK", Endgame::Value(relative(v))));
+        }
+        return None;
+    }
+
+    if m.is(strong, &[(PieceType::Rook, 1)]) && m.is(weak, &[(PieceType::Pawn, 1)]) {
+        return krkp(board, strong, side_to_move).map(|v| ("KRKP", Endgame::Value(relative(v))));
+    }
+
+    let mut factors = [SCALE_NORMAL; 2];
+    let mut name = None;
+    if opposite_bishops(board, &m) {
+        let only_bishops = m.non_pawn(Color::White) == 3 && m.non_pawn(Color::Black) == 3;
+        factors = [if only_bishops { 22 } else { 46 }; 2];
+        name = Some("opposite-coloured bishops");
+    }
+
+    // Without pawns an advantage of a minor piece or less is hard to convert
+    for side in [Color::White, Color::Black] {
+        if m.get(side, PieceType::Pawn) == 0 && m.non_pawn(side) - m.non_pawn(side.opposite()) <= 3 {
+            let factor = if m.non_pawn(side) <= 3 { 0 } else { SCALE_NORMAL / 8 };
+            factors[side as usize] = factors[side as usize].min(factor);
+            name = name.or(Some("no pawns, small advantage"));
+        }
+    }
+
+    name.map(|name| (name, Endgame::Scale(factors)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(fen: &str) -> i32 {
+        let (board, color) = Board::from_fen(fen).unwrap();
+        match probe(&board, color) {
+            Some((_, Endgame::Value(v))) => v,
+            _ => panic!("no value for {}", fen),
+        }
+    }
+
+    #[test]
+    fn kpk_wins_score_the_king_leading_the_pawn() {
+        let behind = value("k7/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        let ahead = value("k7/8/8/8/5K2/8/4P3/8 w - - 0 1");
+        assert!(behind > KNOWN_WIN);
+        assert!(ahead > behind);
+        // The same seen from Black, to move
+        assert_eq!(value("k7/8/8/8/5K2/8/4P3/8 b - - 0 1"), -ahead);
+    }
+
+    #[test]
+    fn kpk_draws_score_zero() {
+        assert_eq!(value("k7/8/8/8/8/8/P7/K7 w - - 0 1"), 0);
+    }
+}
@@ -0,0 +1,173 @@
+// src/epd.rs
+//
+// EPD (Extended Position Description) records, as used by test suites such
+// as WAC, STS or Bratko-Kopec: the first four FEN fields followed by
+// operations, each an opcode, its operands and a ';'. Operands in double
+// quotes may hold spaces. The opcodes the engine understands are bm (best
+// moves), am (moves to avoid), id, c0 (comment) and dm (mate in n moves);
+// the others are kept as they are. Moves are in algebraic notation.
+use crate::san::{self, SanError};
+use crate::{Board, Color, Square};
+
+pub struct Epd {
+    pub board: Board,
+    pub color: Color,
+    // In the order of the record
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+// Splits operations into opcode and operands
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if words.is_empty() {
+                    return Err("empty operation".to_string());
+                }
+                let opcode = words.remove(0);
+                operations.push((opcode, std::mem::take(&mut words)));
+            }
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                if words.is_empty() {
+                    return Err("operation without an opcode".to_string());
+                }
+                words.push(word);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+    // The last ';' is often left out
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+    Ok(operations)
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Epd, String> {
+        let mut rest = line.trim();
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return Err("expected four position fields".to_string());
+            }
+            fields.push(field);
+            rest = tail.trim_start();
+        }
+        let (board, color) = Board::from_fen(&fields.join(" ")).ok_or("invalid position")?;
+        let operations = parse_operations(rest)?;
+        Ok(Epd { board, color, operations })
+    }
+
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id")?.first().map(String::as_str)
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.operands("c0")?.first().map(String::as_str)
+    }
+
+    // The moves of a bm or am operation; an error if one of them is not a
+    // move the engine can play here
+    pub fn moves(&self, opcode: &str) -> Result<Vec<(Square, Square)>, SanError> {
+        self.operands(opcode)
+            .unwrap_or(&[])
+            .iter()
+            .map(|text| san::parse(&self.board, self.color, text))
+            .collect()
+    }
+
+    pub fn best_moves(&self) -> Result<Vec<(Square, Square)>, SanError> {
+        self.moves("bm")
+    }
+
+    pub fn avoid_moves(&self) -> Result<Vec<(Square, Square)>, SanError> {
+        self.moves("am")
+    }
+
+    // Moves to mate in, from dm
+    pub fn direct_mate(&self) -> Option<u32> {
+        self.operands("dm")?.first()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(coords: &str) -> Square {
+        crate::coords_to_square(coords).unwrap()
+    }
+
+    #[test]
+    fn reads_the_position_and_operations() {
+        let position = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -";
+        let epd = Epd::parse(&format!(r#"{} bm Bb5 Bc4; am Ng5; id "test \"1\""; c0 "a comment";"#, position)).unwrap();
+        assert!(epd.color == Color::White);
+        assert_eq!(epd.id(), Some("test \"1\""));
+        assert_eq!(epd.comment(), Some("a comment"));
+        assert_eq!(epd.operands("bm").unwrap(), ["Bb5", "Bc4"]);
+        assert_eq!(epd.best_moves(), Ok(vec![(square("f1"), square("b5")), (square("f1"), square("c4"))]));
+        assert_eq!(epd.avoid_moves(), Ok(vec![(square("f3"), square("g5"))]));
+        assert_eq!(epd.direct_mate(), None);
+    }
+
+    #[test]
+    fn the_last_semicolon_may_be_missing() {
+        let epd = Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - dm 1; id mate").unwrap();
+        assert_eq!(epd.direct_mate(), Some(1));
+        assert_eq!(epd.id(), Some("mate"));
+        assert_eq!(epd.best_moves(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn keeps_unknown_opcodes() {
+        let epd = Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - acd 12; ce +32000;").unwrap();
+        assert_eq!(epd.operations.len(), 2);
+        assert_eq!(epd.operands("ce").unwrap(), ["+32000"]);
+    }
+
+    #[test]
+    fn moves_must_be_playable() {
+        let epd = Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra9;").unwrap();
+        assert_eq!(epd.best_moves(), Err(SanError::Invalid));
+        let epd = Epd::parse("7k/P7/8/8/8/8/8/K7 w - - bm a8=N;").unwrap();
+        assert_eq!(epd.best_moves(), Err(SanError::Unsupported));
+    }
+
+    #[test]
+    fn rejects_broken_records() {
+        assert!(Epd::parse("6k1/5ppp/8/8 w").is_err());
+        assert!(Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - id \"open").is_err());
+        assert!(Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - ;").is_err());
+    }
+}
@@ -0,0 +1,662 @@
+// src/eval.rs
+use crate::endgame::{self, Endgame, SCALE_NORMAL};
+use crate::nnue;
+use crate::params::{self, EvalParams};
+use crate::pawns::{evaluate_pawns, forward, relative_rank};
+use crate::{Board, Color, Piece, PieceType, Square, BISHOP_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS, ROOK_DIRECTIONS};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// A middlegame and an endgame value; the two are blended by game phase
+#[derive(Copy, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+// Game phase: 24 with all pieces on the board, 0 with only kings and pawns
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+pub fn piece_value(params: &EvalParams, piece_type: PieceType) -> Score {
+    let idx = piece_type as usize;
+    Score::new(params.piece_values_mg[idx], params.piece_values_eg[idx])
+}
+
+pub fn get_pst_value(params: &EvalParams, square: Square, piece_type: PieceType, color: Color) -> Score {
+    let file = (square & 0x7) as usize;
+    let rank = (square >> 4) as usize;
+    let row = if color == Color::White { rank } else { 7 - rank };
+    let kind = piece_type as usize;
+    Score::new(params.pst_mg[kind][row][file], params.pst_eg[kind][row][file])
+}
+
+// MAX_PHASE in the opening, 0 when only kings and pawns are left. Promotions
+// can push the raw count above MAX_PHASE, so it is capped.
+pub fn game_phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq) {
+            phase += PHASE_WEIGHTS[piece.kind as usize];
+        }
+    }
+    phase.min(MAX_PHASE)
+}
+
+pub fn taper(score: Score, phase: i32) -> i32 {
+    (score.mg * phase + score.eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// Squares attacked by the pawns of each color, indexed by 0x88 square
+pub fn pawn_attack_map(board: &Board) -> [[bool; 128]; 2] {
+    let mut attacks = [[false; 128]; 2];
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq)
+            && piece.kind == PieceType::Pawn
+        {
+            // White pawns move towards rank 8, which is the low end of the 0x88 board
+            let offsets: [i8; 2] = if piece.color == Color::White { [-17, -15] } else { [15, 17] };
+            for offset in offsets {
+                let to = sq.wrapping_add(offset as u8);
+                if Board::is_valid(to) {
+                    attacks[piece.color as usize][to as usize] = true;
+                }
+            }
+        }
+    }
+    attacks
+}
+
+// Calls `f` for every square attacked by a knight, bishop, rook or queen on
+// `from`. Walks the 0x88 offsets directly instead of generating (and
+// legality checking) moves.
+fn for_each_attack(board: &Board, from: Square, kind: PieceType, mut f: impl FnMut(Square)) {
+    match kind {
+        PieceType::Knight => {
+            for &offset in &KNIGHT_OFFSETS {
+                let to = from.wrapping_add(offset as u8);
+                if Board::is_valid(to) {
+                    f(to);
+                }
+            }
+        }
+        PieceType::Bishop | PieceType::Rook | PieceType::Queen => {
+            let directions: &[i8] = match kind {
+                PieceType::Bishop => &BISHOP_DIRECTIONS,
+                PieceType::Rook => &ROOK_DIRECTIONS,
+                _ => &KING_OFFSETS, // Queen moves in all eight directions
+            };
+            for &dir in directions {
+                let mut to = from;
+                loop {
+                    to = to.wrapping_add(dir as u8);
+                    if !Board::is_valid(to) {
+                        break;
+                    }
+                    f(to);
+                    if board.get_piece(to).is_some() {
+                        break;
+                    }
+                }
+            }
+        }
+        PieceType::Pawn | PieceType::King => {}
+    }
+}
+
+// Attacked squares that are neither occupied by the piece's own side nor
+// attacked by enemy pawns
+fn safe_square_count(board: &Board, from: Square, kind: PieceType, color: Color, enemy_pawn_attacks: &[bool; 128]) -> i32 {
+    let mut count = 0;
+    for_each_attack(board, from, kind, |to| {
+        if !enemy_pawn_attacks[to as usize] && board.get_piece(to).is_none_or(|p| p.color != color) {
+            count += 1;
+        }
+    });
+    count
+}
+
+pub fn mobility(params: &EvalParams, board: &Board, color: Color, pawn_attacks: &[[bool; 128]; 2]) -> Score {
+    let enemy_pawn_attacks = &pawn_attacks[color.opposite() as usize];
+    let mut score = Score::default();
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq)
+            && piece.color == color
+        {
+            let kind = piece.kind as usize;
+            let count = safe_square_count(board, sq, piece.kind, color, enemy_pawn_attacks);
+            score += params.mobility_weights[kind] * (count - params.mobility_baseline[kind]);
+        }
+    }
+    score
+}
+
+fn is_pawn(board: &Board, sq: Square, color: Color) -> bool {
+    Board::is_valid(sq) && matches!(board.get_piece(sq), Some(Piece { kind: PieceType::Pawn, color: c }) if c == color)
+}
+
+fn pawn_on_file(board: &Board, file: u8, color: Color) -> bool {
+    (0u8..8).any(|row| is_pawn(board, row * 16 + file, color))
+}
+
+pub fn square_colour(sq: Square) -> u8 {
+    ((sq >> 4) + (sq & 7)) & 1
+}
+
+// A square in the enemy half that is defended by one of our pawns and can
+// never be attacked by an enemy pawn
+fn is_outpost(board: &Board, sq: Square, color: Color) -> bool {
+    let rank = relative_rank(sq, color);
+    if !(3..=5).contains(&rank) {
+        return false;
+    }
+    let behind = sq.wrapping_sub(forward(color) as u8);
+    if !is_pawn(board, behind.wrapping_sub(1), color) && !is_pawn(board, behind.wrapping_add(1), color) {
+        return false;
+    }
+    let file = (sq & 7) as i32;
+    !(0u8..128).any(|p| {
+        is_pawn(board, p, color.opposite())
+            && ((p & 7) as i32 - file).abs() == 1
+            && relative_rank(p, color) > rank
+    })
+}
+
+// Nothing but empty squares between two squares on the same rank or file
+fn clear_line(board: &Board, from: Square, to: Square) -> bool {
+    let step: i8 = if from >> 4 == to >> 4 {
+        if to > from { 1 } else { -1 }
+    } else if from & 7 == to & 7 {
+        if to > from { 16 } else { -16 }
+    } else {
+        return false;
+    };
+    let mut sq = from.wrapping_add(step as u8);
+    while sq != to {
+        if board.get_piece(sq).is_some() {
+            return false;
+        }
+        sq = sq.wrapping_add(step as u8);
+    }
+    true
+}
+
+// Bishop pair, rooks on open files and the seventh rank, connected rooks,
+// outposts, bad bishops and trapped pieces
+pub fn piece_terms(params: &EvalParams, board: &Board, color: Color, pawn_attacks: &[[bool; 128]; 2]) -> Score {
+    let them = color.opposite();
+    let enemy_pawn_attacks = &pawn_attacks[them as usize];
+    let own_king = board.king_square(color);
+    let enemy_king = board.king_square(them);
+    let mut score = Score::default();
+    let mut bishops = 0;
+    let mut rooks = Vec::new();
+
+    for sq in 0u8..128 {
+        let Some(piece) = board.get_piece(sq) else { continue };
+        if piece.color != color {
+            continue;
+        }
+        let file = sq & 7;
+        let rank = relative_rank(sq, color);
+        match piece.kind {
+            PieceType::Knight if is_outpost(board, sq, color) => score += params.knight_outpost,
+            PieceType::Bishop => {
+                bishops += 1;
+                if is_outpost(board, sq, color) {
+                    score += params.bishop_outpost;
+                }
+                let same_colour_pawns = (0u8..128)
+                    .filter(|&p| is_pawn(board, p, color) && square_colour(p) == square_colour(sq))
+                    .count() as i32;
+                score += params.bad_bishop * same_colour_pawns;
+                if rank == 6 && (file == 0 || file == 7) {
+                    let towards_centre: i8 = if file == 0 { 1 } else { -1 };
+                    let blocker = sq.wrapping_sub(forward(color) as u8).wrapping_add(towards_centre as u8);
+                    if is_pawn(board, blocker, them) {
+                        score += params.trapped_bishop;
+                    }
+                }
+            }
+            PieceType::Rook => {
+                rooks.push(sq);
+                if !pawn_on_file(board, file, color) {
+                    score += if pawn_on_file(board, file, them) { params.rook_semi_open_file } else { params.rook_open_file };
+                }
+                // Only worth it when there is something to attack there
+                let enemy_king_on_eighth = enemy_king.is_some_and(|k| relative_rank(k, color) == 7);
+                let enemy_pawns_on_seventh = (0u8..8).any(|f| {
+                    let p = if color == Color::White { 0x10 | f } else { 0x60 | f };
+                    is_pawn(board, p, them)
+                });
+                if rank == 6 && (enemy_king_on_eighth || enemy_pawns_on_seventh) {
+                    score += params.rook_on_seventh;
+                }
+                if rank == 0
+                    && let Some(king) = own_king
+                    && relative_rank(king, color) == 0
+                {
+                    let king_file = king & 7;
+                    let cornered = (king_file >= 5 && file > king_file) || ((1..=3).contains(&king_file) && file < king_file);
+                    if cornered && safe_square_count(board, sq, PieceType::Rook, color, enemy_pawn_attacks) <= 3 {
+                        score += params.trapped_rook;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if bishops >= 2 {
+        score += params.bishop_pair;
+    }
+    if let [a, b] = rooks[..]
+        && clear_line(board, a, b)
+    {
+        score += params.connected_rooks;
+    }
+    score
+}
+
+// The squares around the king plus three more in front of it
+fn king_zone(king: Square, color: Color) -> Vec<Square> {
+    let mut zone = vec![king];
+    for &offset in &KING_OFFSETS {
+        let sq = king.wrapping_add(offset as u8);
+        if Board::is_valid(sq) {
+            zone.push(sq);
+        }
+    }
+    let ahead = king.wrapping_add((2 * forward(color)) as u8);
+    for sq in [ahead.wrapping_sub(1), ahead, ahead.wrapping_add(1)] {
+        if Board::is_valid(sq) && (sq & 7).abs_diff(king & 7) <= 1 {
+            zone.push(sq);
+        }
+    }
+    zone
+}
+
+// Pawn shield, pawn storm and open files on the king's file and its
+// neighbours, plus the danger from enemy pieces attacking the king zone.
+// Mostly a middlegame concern, the endgame weight is small.
+pub fn king_safety(params: &EvalParams, board: &Board, color: Color) -> Score {
+    let Some(king) = board.king_square(color) else {
+        return Score::default();
+    };
+    let them = color.opposite();
+    let king_file = (king & 7) as i32;
+    let mut shelter = 0;
+
+    for file in (king_file - 1).max(0)..=(king_file + 1).min(7) {
+        // Closest pawn of each side on this file, in front of the king
+        let mut own_rank = 0;
+        let mut enemy_rank = 0;
+        let mut any_own = false;
+        let mut any_enemy = false;
+        for row in 0u8..8 {
+            let sq = row * 16 + file as u8;
+            let Some(Piece { kind: PieceType::Pawn, color: c }) = board.get_piece(sq) else { continue };
+            let rank = relative_rank(sq, color);
+            if c == color {
+                any_own = true;
+                if rank >= relative_rank(king, color) && (own_rank == 0 || rank < own_rank) {
+                    own_rank = rank;
+                }
+            } else {
+                any_enemy = true;
+                if rank >= relative_rank(king, color) && (enemy_rank == 0 || rank < enemy_rank) {
+                    enemy_rank = rank;
+                }
+            }
+        }
+        shelter += params.pawn_shield[own_rank as usize] + params.pawn_storm[enemy_rank as usize];
+        if !any_own {
+            shelter += if any_enemy { params.semi_open_file_near_king } else { params.open_file_near_king };
+        }
+    }
+
+    let zone = king_zone(king, color);
+    let mut attack_units = 0;
+    let mut attackers = 0;
+    for sq in 0u8..128 {
+        let Some(piece) = board.get_piece(sq) else { continue };
+        if piece.color != them {
+            continue;
+        }
+        let mut hits = 0;
+        for_each_attack(board, sq, piece.kind, |to| {
+            if zone.contains(&to) {
+                hits += 1;
+            }
+        });
+        if hits > 0 {
+            attackers += 1;
+            attack_units += hits * params.king_attack_weights[piece.kind as usize];
+        }
+    }
+    // A lone attacker is rarely dangerous
+    let danger = if attackers >= 2 {
+        let table = &params.king_danger;
+        table.get(attack_units as usize).or(table.last()).copied().unwrap_or(0)
+    } else {
+        0
+    };
+
+    Score::new(shelter - danger, -danger / 4)
+}
+
+// Score from the point of view of `color`, which is expected to be the side to move
+pub fn evaluate(board: &Board, color: Color) -> i32 {
+    let endgame = endgame::probe(board, color);
+    if let Some((_, Endgame::Value(value))) = endgame {
+        return value;
+    }
+
+    let score = match nnue::active() {
+        Some(net) => nnue::evaluate(net, board, color),
+        None => classical(board, color),
+    };
+    match endgame {
+        Some((_, Endgame::Scale(factors))) => {
+            let favoured = if score >= 0 { color } else { color.opposite() };
+            score * factors[favoured as usize] / SCALE_NORMAL
+        }
+        _ => score,
+    }
+}
+
+// The hand-crafted evaluation
+fn classical(board: &Board, color: Color) -> i32 {
+    let params = params::get();
+    let mut score = Score::default();
+
+    for sq in 0u8..128 {
+        if !Board::is_valid(sq) {
+            continue;
+        }
+        if let Some(piece) = board.get_piece(sq) {
+            let value = piece_value(&params, piece.kind) + get_pst_value(&params, sq, piece.kind, piece.color);
+            if piece.color == color {
+                score += value;
+            } else {
+                score -= value;
+            }
+        }
+    }
+
+    let pawn_attacks = pawn_attack_map(board);
+    score += mobility(&params, board, color, &pawn_attacks) - mobility(&params, board, color.opposite(), &pawn_attacks);
+    score += evaluate_pawns(&params, board, color, color) - evaluate_pawns(&params, board, color.opposite(), color);
+    score += king_safety(&params, board, color) - king_safety(&params, board, color.opposite());
+    score += piece_terms(&params, board, color, &pawn_attacks) - piece_terms(&params, board, color.opposite(), &pawn_attacks);
+
+    taper(score, game_phase(board))
+}
+
+// Per-term breakdown of `evaluate`, for the `eval` command
+pub struct EvalTrace {
+    terms: Vec<(&'static str, [Score; 2])>, // indexed by color
+    phase: i32,
+    side_to_move: Color,
+    nnue: Option<i32>,
+    endgame: Option<(&'static str, Endgame)>,
+    used: i32,
+}
+
+pub fn trace(board: &Board, side_to_move: Color) -> EvalTrace {
+    // Before taking the parameters: `evaluate` reads them itself, and a
+    // second read lock on the same thread can deadlock behind a writer
+    let used = evaluate(board, side_to_move);
+    let params = params::get();
+    let mut material = [Score::default(); 2];
+    let mut pst = [Score::default(); 2];
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq) {
+            material[piece.color as usize] += piece_value(&params, piece.kind);
+            pst[piece.color as usize] += get_pst_value(&params, sq, piece.kind, piece.color);
+        }
+    }
+
+    let pawn_attacks = pawn_attack_map(board);
+    let per_side = |f: &dyn Fn(Color) -> Score| [f(Color::White), f(Color::Black)];
+    let terms = vec![
+        // Includes the kings, which cancel out in the total
+        ("Material", material),
+        ("PST", pst),
+        ("Mobility", per_side(&|c| mobility(&params, board, c, &pawn_attacks))),
+        ("Pawns", per_side(&|c| evaluate_pawns(&params, board, c, side_to_move))),
+        ("King safety", per_side(&|c| king_safety(&params, board, c))),
+        ("Pieces", per_side(&|c| piece_terms(&params, board, c, &pawn_attacks))),
+    ];
+
+    EvalTrace {
+        terms,
+        phase: game_phase(board),
+        side_to_move,
+        nnue: nnue::active().map(|net| nnue::evaluate(net, board, side_to_move)),
+        endgame: endgame::probe(board, side_to_move),
+        used,
+    }
+}
+
+impl EvalTrace {
+    // Sum of all terms from White's point of view
+    fn total(&self) -> Score {
+        self.terms.iter().fold(Score::default(), |acc, (_, s)| acc + s[0] - s[1])
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter, name: &str, w: Score, b: Score| {
+            let d = w - b;
+            writeln!(
+                f,
+                "{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                name, w.mg, w.eg, b.mg, b.eg, d.mg, d.eg
+            )
+        };
+        writeln!(f, "{:>12} | {:^13} | {:^13} | {:^13}", "Term", "White", "Black", "Total")?;
+        writeln!(f, "{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "", "MG", "EG", "MG", "EG", "MG", "EG")?;
+        writeln!(f, "{}", "-".repeat(13) + &"+---------------".repeat(3))?;
+        for (name, score) in &self.terms {
+            row(f, name, score[0], score[1])?;
+        }
+        writeln!(f, "{}", "-".repeat(13) + &"+---------------".repeat(3))?;
+        let total = self.total();
+        writeln!(f, "{:>12} | {:>13} | {:>13} | {:>6} {:>6}", "Total", "", "", total.mg, total.eg)?;
+        writeln!(f)?;
+        writeln!(f, "Game phase: {} / {}", self.phase, MAX_PHASE)?;
+        let white = taper(total, self.phase);
+        writeln!(f, "Final evaluation: {:+} (White's point of view)", white)?;
+        let relative = if self.side_to_move == Color::White { white } else { -white };
+        let side = if self.side_to_move == Color::White { "White" } else { "Black" };
+        write!(f, "Side to move ({}): {:+}", side, relative)?;
+        if let Some(score) = self.nnue {
+            write!(f, "\nNNUE evaluation (in use): {:+}", score)?;
+        }
+        match &self.endgame {
+            Some((name, Endgame::Value(_))) => write!(f, "\nEndgame: {}, specialised evaluation", name)?,
+            Some((name, Endgame::Scale([white, black]))) => write!(
+                f,
+                "\nEndgame: {}, scaled by {}/{} when White is better, {}/{} when Black is",
+                name, white, SCALE_NORMAL, black, SCALE_NORMAL
+            )?,
+            None => {}
+        }
+        write!(f, "\nEvaluation used by the search (side to move): {:+}", self.used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap().0
+    }
+
+    // The same position with the colours swapped and the board flipped
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+                .collect()
+        };
+        let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'9' - rank + b'0') as char),
+            _ => fields[3].to_string(),
+        };
+        format!("{} {} {} {} 0 1", placement.join("/"), side, swap_case(fields[2]), en_passant)
+    }
+
+    #[test]
+    fn evaluation_is_colour_symmetric() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/5k2/8/8/3P4/8/4K3/8 b - - 0 1",
+        ] {
+            let (board, color) = Board::from_fen(fen).unwrap();
+            let (mirrored, mirrored_color) = Board::from_fen(&mirror(fen)).unwrap();
+            assert!(mirrored_color == color.opposite());
+            assert_eq!(evaluate(&board, color), evaluate(&mirrored, mirrored_color), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn game_phase_counts_the_pieces() {
+        assert_eq!(game_phase(&board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")), MAX_PHASE);
+        assert_eq!(game_phase(&board("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1")), 0);
+        assert_eq!(game_phase(&board("r3k3/8/8/8/8/8/8/R3K1N1 w - - 0 1")), 5);
+        // Promoted queens do not push it past the opening
+        assert_eq!(game_phase(&board("4k3/8/8/8/8/8/8/QQQQQQQK w - - 0 1")), MAX_PHASE);
+    }
+
+    #[test]
+    fn taper_blends_middlegame_and_endgame() {
+        let score = Score::new(100, 20);
+        assert_eq!(taper(score, MAX_PHASE), 100);
+        assert_eq!(taper(score, 0), 20);
+        assert_eq!(taper(score, MAX_PHASE / 2), 60);
+    }
+
+    #[test]
+    fn mobility_counts_safe_squares() {
+        let params = EvalParams::default();
+        let knight = params.mobility_weights[PieceType::Knight as usize];
+        let baseline = params.mobility_baseline[PieceType::Knight as usize];
+        let mobility_of = |fen: &str| {
+            let board = board(fen);
+            mobility(&params, &board, Color::White, &pawn_attack_map(&board))
+        };
+
+        // All eight squares of a centralised knight
+        assert_eq!(mobility_of("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1"), knight * (8 - baseline));
+        // Not b5, which the c6 pawn attacks, but taking that pawn counts
+        assert_eq!(mobility_of("4k3/8/2p5/8/3N4/8/8/4K3 w - - 0 1"), knight * (7 - baseline));
+        // Nor squares taken by its own pieces
+        assert_eq!(mobility_of("4k3/8/8/8/3N4/8/2P5/4K3 w - - 0 1"), knight * (7 - baseline));
+        // A cornered knight is below the baseline
+        assert_eq!(mobility_of("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"), knight * (2 - baseline));
+    }
+
+    #[test]
+    fn king_safety_scores_shelter_and_attackers() {
+        let params = EvalParams::default();
+        let safety = |fen: &str| king_safety(&params, &board(fen), Color::White);
+
+        // f2, g2 and h2 in front of the king cost nothing
+        assert_eq!(safety("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1"), Score::default());
+        // Without them all three files are open
+        let bare = params.pawn_shield[0] + params.open_file_near_king;
+        assert_eq!(safety("6k1/8/8/8/8/8/8/6K1 w - - 0 1"), Score::new(3 * bare, 0));
+
+        // A rook hitting f3, g3 and h3 on its own is ignored
+        assert_eq!(safety("6k1/8/8/8/8/4r3/5PPP/6K1 w - - 0 1"), Score::default());
+        // With a knight hitting f3 and h3 as well it is dangerous
+        let units = 3 * params.king_attack_weights[PieceType::Rook as usize]
+            + 2 * params.king_attack_weights[PieceType::Knight as usize];
+        let danger = params.king_danger[units as usize];
+        assert_eq!(safety("6k1/8/8/6n1/8/4r3/5PPP/6K1 w - - 0 1"), Score::new(-danger, -danger / 4));
+    }
+
+    #[test]
+    fn piece_terms_in_known_positions() {
+        let params = EvalParams::default();
+        let terms = |fen: &str| {
+            let board = board(fen);
+            piece_terms(&params, &board, Color::White, &pawn_attack_map(&board))
+        };
+
+        assert_eq!(terms("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), params.bishop_pair);
+        assert_eq!(terms("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), Score::default());
+
+        assert_eq!(terms("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), params.rook_open_file);
+        assert_eq!(terms("4k3/p7/8/8/8/8/8/R3K3 w - - 0 1"), params.rook_semi_open_file);
+        assert_eq!(terms("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1"), params.rook_open_file * 2 + params.connected_rooks);
+        // The h1 rook is boxed in by the king on f1
+        assert_eq!(terms("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1"), params.trapped_rook);
+
+        // d5 is covered by e4 and no black pawn can ever chase the knight away
+        assert_eq!(terms("4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1"), params.knight_outpost);
+        assert_eq!(terms("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1"), Score::default());
+
+        assert_eq!(terms("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1"), params.trapped_bishop);
+        // Two pawns on the dark squares of the c1 bishop
+        assert_eq!(terms("4k3/8/8/8/8/8/1P1P4/2B1K3 w - - 0 1"), params.bad_bishop * 2);
+    }
+}
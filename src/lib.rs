@@ -0,0 +1,1507 @@
+// src/lib.rs
+pub mod async_search;
+pub mod bitbase;
+pub mod book;
+pub mod dtm;
+pub mod endgame;
+pub mod epd;
+pub mod eval;
+pub mod nnue;
+pub mod params;
+pub mod pgn;
+pub mod pawns;
+pub mod polyglot;
+pub mod san;
+pub mod skill;
+pub mod syzygy;
+mod tablebase;
+pub mod tt;
+pub mod uci;
+pub mod zobrist;
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use async_search::{SearchEvent, SearchHandle, SearchLimits};
+use book::Book;
+use eval::evaluate;
+use skill::{Skill, MAX_SKILL, SKILL_MULTIPV};
+use nnue::{Accumulator, Network};
+use tt::{Bound, TranspositionTable};
+pub fn coords_to_square(coord: &str) -> Option<Square> {
+    if coord.len() != 2 {
+        return None;
+    }
+    let chars: Vec<char> = coord.chars().collect();
+    let file = chars[0].to_ascii_lowercase() as u8;
+    let rank = chars[1] as u8;
+
+    if !(b'a'..=b'h').contains(&file) || !(b'1'..=b'8').contains(&rank) {
+        return None;
+    }
+
+    let file_idx = file - b'a';
+    let rank_idx = 7 - (rank - b'1'); // Invert the rank to match 0x88 board representation
+    Some(rank_idx * 16 + file_idx)
+}
+
+pub const MAX_DEPTH: i32 = 4;
+pub const MAX_PLY: i32 = 64;
+const INFINITY: i32 = i32::MAX;
+// Mate on the board; a side mated `ply` plies from the root scores
+// -MATE + ply, so shorter mates score higher for the winner
+const MATE: i32 = INFINITY - 1;
+
+// Mates at a known distance: one the search found, or one a DTM table gives
+pub fn is_mate_score(score: i32) -> bool {
+    let score = score.abs();
+    let dtm_mates = dtm::DTM_WIN - MAX_PLY - dtm::MAX_PLIES as i32..=dtm::DTM_WIN;
+    score >= MATE - MAX_PLY || dtm_mates.contains(&score)
+}
+
+// Plies from the root to the mate a mate score stands for
+pub fn mate_plies(score: i32) -> i32 {
+    let score = score.abs();
+    if score >= MATE - MAX_PLY { MATE - score } else { dtm::DTM_WIN - score }
+}
+
+// Results the search or a tablebase proved, Syzygy wins included; the
+// evaluation's known wins stay below them
+pub fn is_decisive_score(score: i32) -> bool {
+    score.abs() >= syzygy::TB_WIN - MAX_PLY
+}
+
+// Decisive scores count plies from the root, the transposition table keeps
+// them counting from the node they were found at
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if !is_decisive_score(score) {
+        score
+    } else if score > 0 {
+        score + ply
+    } else {
+        score - ply
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    score_to_tt(score, -ply)
+}
+const DEFAULT_THREADS: usize = 1;
+const DEFAULT_HASH_MB: usize = 16;
+const NODE_BATCH: u64 = 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(1000);
+
+// Add MVV-LVA table for move ordering
+const MVV_LVA: [[i32; 6]; 6] = [
+    [105, 205, 305, 405, 505, 605], // Pawn captures
+    [104, 204, 304, 404, 504, 604], // Knight captures
+    [103, 203, 303, 403, 503, 603], // Bishop captures
+    [102, 202, 302, 402, 502, 602], // Rook captures
+    [101, 201, 301, 401, 501, 601], // Queen captures
+    [100, 200, 300, 400, 500, 600], // King captures
+];
+
+fn get_mvv_lva_score(attacker: PieceType, victim: PieceType) -> i32 {
+    let attacker_idx = match attacker {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    };
+    let victim_idx = match victim {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    };
+    MVV_LVA[attacker_idx][victim_idx]
+}
+
+// Move struct for move ordering
+#[derive(Clone, Copy)]
+struct Move {
+    from: Square,
+    to: Square,
+    score: i32,
+}
+
+fn get_moves_with_scores(board: &Board, color: Color) -> Vec<Move> {
+    let mut moves = Vec::new();
+    
+    for from in 0u8..128 {
+        if !Board::is_valid(from) {
+            continue;
+        }
+        if let Some(piece) = board.get_piece(from)
+            && piece.color == color
+        {
+            let legal_moves = board.generate_legal_moves_for_piece(from);
+            for &to in &legal_moves {
+                let mut clone = board.clone();
+                clone.make_move(from, to);
+                if !is_in_check(&clone, color) {
+                    let mut score = evaluate(&clone, color);
+
+                    // Add MVV-LVA score for captures
+                    if let Some(captured_piece) = board.get_piece(to)
+                        && captured_piece.color != color
+                    {
+                        score += get_mvv_lva_score(piece.kind, captured_piece.kind);
+                    }
+
+                    moves.push(Move { from, to, score });
+                }
+            }
+        }
+    }
+    
+    // Sort moves by score in descending order
+    moves.sort_by_key(|mv| std::cmp::Reverse(mv.score));
+    moves
+}
+
+// Search the transposition table move first, it is usually the best one
+fn order_tt_move(moves: &mut [Move], tt_move: Option<(Square, Square)>) {
+    if let Some((from, to)) = tt_move
+        && let Some(idx) = moves.iter().position(|mv| mv.from == from && mv.to == to)
+    {
+        moves[..=idx].rotate_right(1);
+    }
+}
+
+// Flags shared by all threads of one search. While `pondering` is set the
+// main thread keeps deepening past its depth limit, waiting for a ponder hit.
+pub struct SearchShared {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+    depth_limit: i32,
+    node_limit: Option<u64>,
+    time_limit: Option<Duration>,
+    // When the clock started, in milliseconds after `start`: 0, or the
+    // `ponderhit`
+    clock_start_ms: AtomicU64,
+    completed_depth: AtomicI32,
+    nodes: AtomicU64,
+    start: Instant,
+    // Root moves allowed by the tablebases, probed once by the first thread
+    // to get there
+    tb_root_moves: OnceLock<Option<Vec<(Square, Square)>>>,
+}
+
+impl SearchShared {
+    pub fn new(limits: &SearchLimits) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            pondering: AtomicBool::new(limits.ponder || limits.infinite),
+            depth_limit: if limits.infinite { MAX_PLY } else { limits.depth },
+            node_limit: limits.nodes,
+            time_limit: limits.movetime,
+            clock_start_ms: AtomicU64::new(0),
+            completed_depth: AtomicI32::new(0),
+            nodes: AtomicU64::new(0),
+            start: Instant::now(),
+            tb_root_moves: OnceLock::new(),
+        }
+    }
+
+    fn stop(&self) {
+        self.pondering.store(false, Ordering::SeqCst);
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    // The clock does not run while pondering
+    fn out_of_time(&self) -> bool {
+        let Some(limit) = self.time_limit else { return false };
+        let started = Duration::from_millis(self.clock_start_ms.load(Ordering::SeqCst));
+        !self.pondering.load(Ordering::SeqCst) && self.start.elapsed().saturating_sub(started) >= limit
+    }
+
+    // The opponent played the expected move: from now on this is a normal
+    // search, and if it is already past its depth limit it can finish now
+    fn ponderhit(&self) {
+        self.clock_start_ms.store(self.start.elapsed().as_millis() as u64, Ordering::SeqCst);
+        self.pondering.store(false, Ordering::SeqCst);
+        if self.completed_depth.load(Ordering::SeqCst) >= self.depth_limit {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+// Progress reporting state, only the main thread has one
+struct Reporter<'a> {
+    callback: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    last_progress: Instant,
+    depth: i32,
+    current_move: Option<(Square, Square)>,
+    current_move_number: usize,
+}
+
+// Per-thread search state; the transposition table and shared flags are common
+struct SearchContext<'a> {
+    tt: &'a TranspositionTable,
+    shared: &'a SearchShared,
+    nodes: u64,
+    reporter: Option<Reporter<'a>>,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(
+        tt: &'a TranspositionTable,
+        shared: &'a SearchShared,
+        callback: Option<&'a mut (dyn FnMut(&SearchEvent) + Send)>,
+    ) -> Self {
+        Self {
+            tt,
+            shared,
+            nodes: 0,
+            reporter: callback.map(|callback| Reporter {
+                callback,
+                last_progress: Instant::now(),
+                depth: 0,
+                current_move: None,
+                current_move_number: 0,
+            }),
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.shared.stop.load(Ordering::Relaxed)
+    }
+
+    // Node counts are published to the shared counter in batches. The node
+    // and time limits only apply once the first iteration is done, so there
+    // always is a move to play.
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODE_BATCH) {
+            let total = self.shared.nodes.fetch_add(NODE_BATCH, Ordering::Relaxed) + NODE_BATCH;
+            let out_of_nodes = self.shared.node_limit.is_some_and(|limit| total >= limit);
+            if (out_of_nodes || self.shared.out_of_time()) && self.shared.completed_depth.load(Ordering::SeqCst) >= 1 {
+                self.shared.stop.store(true, Ordering::SeqCst);
+            }
+            self.report_progress();
+        }
+    }
+
+    fn flush_nodes(&mut self) {
+        self.shared.nodes.fetch_add(self.nodes % NODE_BATCH, Ordering::Relaxed);
+        self.nodes -= self.nodes % NODE_BATCH;
+    }
+
+    fn set_current_move(&mut self, depth: i32, mv: (Square, Square), number: usize) {
+        if let Some(reporter) = self.reporter.as_mut() {
+            reporter.depth = depth;
+            reporter.current_move = Some(mv);
+            reporter.current_move_number = number;
+        }
+    }
+
+    fn report_progress(&mut self) {
+        let nodes = self.shared.nodes.load(Ordering::Relaxed);
+        let elapsed = self.shared.start.elapsed();
+        let Some(reporter) = self.reporter.as_mut() else { return };
+        if reporter.last_progress.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        reporter.last_progress = Instant::now();
+        if let Some(current_move) = reporter.current_move {
+            (reporter.callback)(&SearchEvent::Progress {
+                depth: reporter.depth,
+                nodes,
+                elapsed,
+                current_move,
+                current_move_number: reporter.current_move_number,
+            });
+        }
+    }
+
+    fn report_iteration(&mut self, board: &Board, color: Color, depth: i32, multipv: usize, best_move: (Square, Square), score: i32) {
+        if self.reporter.is_none() {
+            return;
+        }
+        self.flush_nodes();
+        let pv = extract_pv(board, color, best_move, self.tt, depth as usize);
+        let nodes = self.shared.nodes.load(Ordering::Relaxed);
+        let elapsed = self.shared.start.elapsed();
+        if let Some(reporter) = self.reporter.as_mut() {
+            (reporter.callback)(&SearchEvent::Iteration {
+                depth,
+                multipv,
+                score,
+                nodes,
+                elapsed,
+                pv,
+            });
+        }
+    }
+}
+
+// `ply` counts the moves from the root to this node
+fn negamax(
+    ctx: &mut SearchContext,
+    board: &Board,
+    depth: i32,
+    ply: i32,
+    mut alpha: i32,
+    beta: i32,
+    color: Color,
+) -> i32 {
+    ctx.count_node();
+    // Tablebase results are exact, there is nothing left to search
+    if let Some(dtm) = dtm::probe(board, color) {
+        return dtm.score(ply);
+    }
+    if let Some(wdl) = syzygy::probe_wdl(board, color) {
+        return wdl.score(ply);
+    }
+    // KPK is known exactly, there is nothing to gain from searching on
+    if depth == 0 || bitbase::probe_kpk(board, color).is_some() {
+        return evaluate(board, color);
+    }
+
+    let key = zobrist::hash(board, color);
+    let alpha_orig = alpha;
+    let mut tt_move = None;
+    if let Some(entry) = ctx.tt.probe(key) {
+        tt_move = entry.best_move;
+        let score = score_from_tt(entry.score, ply);
+        if entry.depth >= depth {
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+    }
+
+    let mut moves = get_moves_with_scores(board, color);
+    if moves.is_empty() {
+        if is_in_check(board, color) {
+            return -MATE + ply; // Checkmate
+        }
+        return 0; // Stalemate
+    }
+    order_tt_move(&mut moves, tt_move);
+
+    let mut best_score = -INFINITY;
+    let mut best_move = None;
+    for mv in moves {
+        let mut clone = board.clone();
+        clone.make_move(mv.from, mv.to);
+        let score = -negamax(ctx, &clone, depth - 1, ply + 1, -beta, -alpha, color.opposite());
+        if ctx.stopped() {
+            return 0;
+        }
+        if score > best_score {
+            best_score = score;
+            best_move = Some((mv.from, mv.to));
+        }
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break; // Beta cutoff
+        }
+    }
+
+    let bound = if best_score >= beta {
+        Bound::Lower
+    } else if best_score <= alpha_orig {
+        Bound::Upper
+    } else {
+        Bound::Exact
+    };
+    ctx.tt.store(key, depth, score_to_tt(best_score, ply), bound, best_move);
+
+    best_score
+}
+
+// Root moves listed in `excluded` are skipped; MultiPV uses this to find the
+// next best move after the ones it has already reported
+fn search_root(
+    ctx: &mut SearchContext,
+    board: &Board,
+    color: Color,
+    depth: i32,
+    excluded: &[(Square, Square)],
+) -> Option<((Square, Square), i32)> {
+    let key = zobrist::hash(board, color);
+    let mut moves = get_moves_with_scores(board, color);
+    moves.retain(|mv| !excluded.contains(&(mv.from, mv.to)));
+    if let Some(tb_moves) = ctx.shared.tb_root_moves.get_or_init(|| syzygy::root_moves(board, color)) {
+        moves.retain(|mv| tb_moves.contains(&(mv.from, mv.to)));
+    }
+    order_tt_move(&mut moves, ctx.tt.probe(key).and_then(|entry| entry.best_move));
+
+    let mut best: Option<((Square, Square), i32)> = None;
+    let mut alpha = -INFINITY;
+    let beta = INFINITY;
+    for (i, mv) in moves.into_iter().enumerate() {
+        ctx.set_current_move(depth, (mv.from, mv.to), excluded.len() + i + 1);
+        let mut clone = board.clone();
+        clone.make_move(mv.from, mv.to);
+        let score = -negamax(ctx, &clone, depth - 1, 1, -beta, -alpha, color.opposite());
+        if ctx.stopped() {
+            return None; // Unfinished iteration, discard it
+        }
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some(((mv.from, mv.to), score));
+        }
+        alpha = alpha.max(score);
+    }
+
+    if let Some((best_move, score)) = best
+        && excluded.is_empty()
+    {
+        ctx.tt.store(key, depth, score, Bound::Exact, Some(best_move));
+    }
+    best
+}
+
+// Lazy SMP depth skipping: helper threads skip some iterations so that they
+// spread out over different depths instead of duplicating the main thread
+const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+struct ThreadResult {
+    best_move: Option<(Square, Square)>,
+    score: i32,
+    depth: i32,
+}
+
+fn iterative_deepening(ctx: &mut SearchContext, board: &Board, color: Color, thread_id: usize) -> ThreadResult {
+    let mut result = ThreadResult {
+        best_move: None,
+        score: -INFINITY,
+        depth: 0,
+    };
+
+    // Helpers keep deepening until the main thread stops them
+    for depth in 1..=MAX_PLY {
+        if thread_id > 0 {
+            let idx = (thread_id - 1) % SKIP_SIZE.len();
+            if ((depth + SKIP_PHASE[idx]) / SKIP_SIZE[idx]) % 2 != 0 {
+                continue;
+            }
+        }
+        match search_root(ctx, board, color, depth, &[]) {
+            Some((best_move, score)) => {
+                result = ThreadResult {
+                    best_move: Some(best_move),
+                    score,
+                    depth,
+                };
+                ctx.report_iteration(board, color, depth, 1, best_move, score);
+            }
+            None => break,
+        }
+
+        if thread_id == 0 {
+            ctx.shared.completed_depth.store(depth, Ordering::SeqCst);
+            if depth >= ctx.shared.depth_limit && !ctx.shared.pondering.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+// Every thread votes for its best move, weighted by score and completed depth.
+// Ties are broken by depth and then thread order, so the choice only depends
+// on the thread results and not on the order in which threads finished.
+fn select_best_thread(results: &[ThreadResult]) -> Option<(Square, Square)> {
+    let min_score = results.iter().filter(|r| r.best_move.is_some()).map(|r| r.score).min()?;
+
+    let mut votes: Vec<((Square, Square), i64)> = Vec::new();
+    for r in results {
+        if let Some(mv) = r.best_move {
+            let weight = (r.score as i64 - min_score as i64 + 14) * r.depth as i64;
+            match votes.iter_mut().find(|(m, _)| *m == mv) {
+                Some((_, total)) => *total += weight,
+                None => votes.push((mv, weight)),
+            }
+        }
+    }
+    let votes_for = |mv: (Square, Square)| votes.iter().find(|(m, _)| *m == mv).map_or(0, |(_, v)| *v);
+
+    let mut best: Option<&ThreadResult> = None;
+    for r in results {
+        let Some(mv) = r.best_move else { continue };
+        best = match best {
+            Some(b) => {
+                let (current, candidate) = (votes_for(b.best_move.unwrap()), votes_for(mv));
+                if candidate > current || (candidate == current && r.depth > b.depth) {
+                    Some(r)
+                } else {
+                    Some(b)
+                }
+            }
+            None => Some(r),
+        };
+    }
+    best.and_then(|r| r.best_move)
+}
+
+#[derive(Clone)]
+pub struct Engine {
+    pub tt: Arc<TranspositionTable>,
+    pub hash_mb: usize,
+    pub threads: usize,
+    pub ponder: bool,
+    pub multipv: usize,
+    pub skill: Skill,
+    pub book: Option<Arc<Book>>,
+    // Play the book's most played move rather than a weighted random one
+    pub best_book_move: bool,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            hash_mb: DEFAULT_HASH_MB,
+            threads: DEFAULT_THREADS,
+            ponder: true,
+            multipv: 1,
+            skill: Skill::new(MAX_SKILL),
+            book: None,
+            best_book_move: false,
+        }
+    }
+
+    // Move from the opening book, if there is one for the position
+    pub fn book_move(&self, board: &Board, color: Color) -> Option<(Square, Square)> {
+        self.book.as_ref()?.pick(board, color, self.best_book_move)
+    }
+
+    // A reduced skill level also limits how deep and wide we search
+    fn search_limits(&self, mut limits: SearchLimits) -> SearchLimits {
+        if self.skill.enabled() {
+            limits.depth = limits.depth.min(self.skill.depth());
+            let nodes = self.skill.node_limit();
+            limits.nodes = Some(limits.nodes.map_or(nodes, |n| n.min(nodes)));
+        }
+        limits
+    }
+
+    pub fn resize_hash(&mut self, size_mb: usize) {
+        self.hash_mb = size_mb;
+        self.tt = Arc::new(TranspositionTable::new(size_mb));
+    }
+}
+
+fn search_position<'a>(
+    board: &Board,
+    color: Color,
+    threads: usize,
+    tt: &'a TranspositionTable,
+    shared: &'a SearchShared,
+    callback: Option<&'a mut (dyn FnMut(&SearchEvent) + Send)>,
+) -> Option<(Square, Square)> {
+    let mut callback = callback;
+    let results: Vec<ThreadResult> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads.max(1))
+            .map(|thread_id| {
+                let callback = if thread_id == 0 { callback.take() } else { None };
+                s.spawn(move || {
+                    let mut ctx = SearchContext::new(tt, shared, callback);
+                    let result = iterative_deepening(&mut ctx, board, color, thread_id);
+                    ctx.flush_nodes();
+                    if thread_id == 0 {
+                        // Main thread is done, helpers stop with it
+                        shared.stop.store(true, Ordering::SeqCst);
+                    }
+                    result
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    select_best_thread(&results)
+}
+
+// Searches with the engine settings: weaker skill levels choose among several
+// MultiPV lines, otherwise MultiPV or a plain (possibly parallel) search
+fn engine_search<'a>(
+    engine: &'a Engine,
+    board: &Board,
+    color: Color,
+    shared: &'a SearchShared,
+    callback: Option<&'a mut (dyn FnMut(&SearchEvent) + Send)>,
+) -> Option<(Square, Square)> {
+    if engine.skill.enabled() {
+        let num_pv = engine.multipv.max(SKILL_MULTIPV);
+        let results = search_multipv(board, color, num_pv, &engine.tt, shared, callback);
+        results.last().and_then(|lines| engine.skill.pick_move(lines))
+    } else if engine.multipv > 1 {
+        let results = search_multipv(board, color, engine.multipv, &engine.tt, shared, callback);
+        results.last().map(|lines| lines[0].best_move)
+    } else {
+        search_position(board, color, engine.threads, &engine.tt, shared, callback)
+    }
+}
+
+// Score and depth of the last iteration's best line, kept from the search events
+type EvalSlot = Arc<Mutex<Option<(i32, i32)>>>;
+
+fn record_eval(slot: &EvalSlot, event: &SearchEvent) {
+    if let SearchEvent::Iteration { depth, multipv: 1, score, .. } = *event {
+        *slot.lock().unwrap() = Some((score, depth));
+    }
+}
+
+fn find_best_move(board: &Board, color: Color, engine: &Engine, eval: &EvalSlot) -> Option<(Square, Square)> {
+    let limits = engine.search_limits(SearchLimits {
+        depth: MAX_DEPTH,
+        ponder: false,
+        infinite: false,
+        nodes: None,
+        movetime: None,
+    });
+    let shared = SearchShared::new(&limits);
+    let mut callback = |event: &SearchEvent| record_eval(eval, event);
+    engine_search(engine, board, color, &shared, Some(&mut callback))
+}
+
+#[derive(Clone)]
+pub struct PvLine {
+    pub best_move: (Square, Square),
+    pub score: i32,
+    pub pv: Vec<(Square, Square)>,
+}
+
+// Follow best moves through the transposition table, starting with `first`
+fn extract_pv(board: &Board, color: Color, first: (Square, Square), tt: &TranspositionTable, max_len: usize) -> Vec<(Square, Square)> {
+    let mut pv = vec![first];
+    let mut board = board.clone();
+    board.make_move(first.0, first.1);
+    let mut color = color.opposite();
+    while pv.len() < max_len {
+        match expected_reply(&board, color, tt) {
+            Some(mv) => {
+                pv.push(mv);
+                board.make_move(mv.0, mv.1);
+                color = color.opposite();
+            }
+            None => break,
+        }
+    }
+    pv
+}
+
+// Returns the best `num_pv` root moves for every completed depth. Each depth
+// is searched in passes: a pass excludes the moves found by earlier passes,
+// so it finds the next best move and its exact score.
+pub fn search_multipv<'a>(
+    board: &Board,
+    color: Color,
+    num_pv: usize,
+    tt: &'a TranspositionTable,
+    shared: &'a SearchShared,
+    callback: Option<&'a mut (dyn FnMut(&SearchEvent) + Send)>,
+) -> Vec<Vec<PvLine>> {
+    let mut ctx = SearchContext::new(tt, shared, callback);
+    let mut results = Vec::new();
+
+    for depth in 1..=MAX_PLY {
+        let mut lines: Vec<PvLine> = Vec::new();
+        let mut excluded = Vec::new();
+        while lines.len() < num_pv {
+            match search_root(&mut ctx, board, color, depth, &excluded) {
+                Some((best_move, score)) => {
+                    excluded.push(best_move);
+                    lines.push(PvLine {
+                        best_move,
+                        score,
+                        pv: extract_pv(board, color, best_move, tt, depth as usize),
+                    });
+                }
+                None => break,
+            }
+        }
+        if ctx.stopped() || lines.is_empty() {
+            break;
+        }
+        lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+        for (i, line) in lines.iter().enumerate() {
+            ctx.report_iteration(board, color, depth, i + 1, line.best_move, line.score);
+        }
+        results.push(lines);
+
+        shared.completed_depth.store(depth, Ordering::SeqCst);
+        if depth >= shared.depth_limit && !shared.pondering.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+
+    results
+}
+
+// The reply we expect after our best move, taken from the transposition table
+fn expected_reply(board: &Board, color: Color, tt: &TranspositionTable) -> Option<(Square, Square)> {
+    let (from, to) = tt.probe(zobrist::hash(board, color))?.best_move?;
+    match board.get_piece(from) {
+        Some(piece) if piece.color == color && board.generate_legal_moves_for_piece(from).contains(&to) => {
+            Some((from, to))
+        }
+        _ => None,
+    }
+}
+
+// A search of the position after the expected reply, running while the user thinks
+pub struct Ponder {
+    pub expected: (Square, Square),
+    search: SearchHandle,
+    eval: EvalSlot,
+}
+
+impl Ponder {
+    pub fn start(board: &Board, engine_color: Color, engine: &Engine) -> Option<Ponder> {
+        let expected = expected_reply(board, engine_color.opposite(), &engine.tt)?;
+        let mut ponder_board = board.clone();
+        ponder_board.make_move(expected.0, expected.1);
+
+        let limits = SearchLimits {
+            depth: MAX_DEPTH,
+            ponder: true,
+            infinite: false,
+            nodes: None,
+            movetime: None,
+        };
+        let eval = EvalSlot::default();
+        let slot = Arc::clone(&eval);
+        let search =
+            SearchHandle::start(engine, &ponder_board, engine_color, limits, move |event| record_eval(&slot, event));
+        Some(Ponder { expected, search, eval })
+    }
+
+    pub fn stop(self) {
+        self.search.stop();
+        self.search.wait();
+    }
+
+    fn ponderhit(self) -> Option<(Square, Square)> {
+        self.search.ponderhit();
+        self.search.wait().best_move
+    }
+}
+
+// A move the engine played, with the score (for the engine) and depth of the
+// search's last iteration; book moves have none
+pub struct EngineMove {
+    pub mv: (Square, Square),
+    pub eval: Option<(i32, i32)>,
+}
+
+pub fn engine_make_move(
+    board: &mut Board,
+    engine_color: Color,
+    engine: &Engine,
+    ponder: Option<Ponder>,
+) -> Option<EngineMove> {
+    // The book is consulted before any search
+    let eval = EvalSlot::default();
+    let best_move = match (engine.book_move(board, engine_color), ponder) {
+        (Some(book_move), ponder) => {
+            if let Some(ponder) = ponder {
+                ponder.stop();
+            }
+            println!("Book move.");
+            Some(book_move)
+        }
+        (None, Some(ponder)) => {
+            println!("Ponder hit.");
+            let slot = Arc::clone(&ponder.eval);
+            let best_move = ponder.ponderhit();
+            *eval.lock().unwrap() = *slot.lock().unwrap();
+            best_move
+        }
+        (None, None) => find_best_move(board, engine_color, engine, &eval),
+    };
+    if let Some((from, to)) = best_move {
+        println!("Engine plays: {}", san::to_san(board, engine_color, (from, to)));
+        board.make_move(from, to);
+        let eval = *eval.lock().unwrap();
+        Some(EngineMove { mv: (from, to), eval })
+    } else {
+        println!("Engine has no legal moves.");
+        None
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceType,
+}
+
+pub type Square = u8; // 0..127
+
+#[derive(Clone)]
+pub struct Board {
+    pub squares: [Option<Piece>; 128], // 0x88 board
+    // Castling rights: white short, white long, black short, black long
+    pub castling: [bool; 4],
+    // The square a pawn skipped with a double push on the last move, when
+    // an enemy pawn stands next to it and may take en passant
+    pub en_passant: Option<Square>,
+    // NNUE hidden layer sums, kept up to date by `make_move` while a network is in use
+    accumulator: Option<Box<Accumulator>>,
+}
+
+// King from, king to, rook from and rook to of each castling right, in the
+// order of `Board::castling`
+const CASTLING_MOVES: [(Square, Square, Square, Square); 4] = [
+    (0x74, 0x76, 0x77, 0x75), // e1g1, h1f1
+    (0x74, 0x72, 0x70, 0x73), // e1c1, a1d1
+    (0x04, 0x06, 0x07, 0x05), // e8g8, h8f8
+    (0x04, 0x02, 0x00, 0x03), // e8c8, a8d8
+];
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Board {
+    pub fn new() -> Self {
+        Self {
+            squares: [None; 128],
+            castling: [false; 4],
+            en_passant: None,
+            accumulator: None,
+        }
+    }
+
+    pub fn is_valid(square: Square) -> bool {
+        square & 0x88 == 0
+    }
+
+    pub fn set_piece(&mut self, square: Square, piece: Piece) {
+        if Self::is_valid(square) {
+            self.squares[square as usize] = Some(piece);
+            self.accumulator = None;
+        }
+    }
+
+    pub fn get_piece(&self, square: Square) -> Option<Piece> {
+        if Self::is_valid(square) {
+            self.squares[square as usize]
+        } else {
+            None
+        }
+    }
+
+    // Parses piece placement, side to move, castling rights and the en
+    // passant square. The move counters are ignored, and so are castling and
+    // en passant fields that are missing or hold something else, as in the
+    // position lines of training data. Castling rights without the king and
+    // rook on their squares are dropped.
+    pub fn from_fen(fen: &str) -> Option<(Board, Color)> {
+        let mut fields = fen.split_whitespace();
+        let rows: Vec<&str> = fields.next()?.split('/').collect();
+        if rows.len() != 8 {
+            return None;
+        }
+
+        let mut board = Board::new();
+        for (rank, row) in rows.iter().enumerate() {
+            let mut file = 0u8;
+            for c in row.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    file += empty as u8;
+                    continue;
+                }
+                if file >= 8 {
+                    return None;
+                }
+                board.set_piece(rank as u8 * 16 + file, piece_from_char(c)?);
+                file += 1;
+            }
+            if file != 8 {
+                return None;
+            }
+        }
+
+        let color = match fields.next() {
+            Some("w") | None => Color::White,
+            Some("b") => Color::Black,
+            _ => return None,
+        };
+        if let Some(rights) = fields.next().filter(|f| f.chars().all(|c| "KQkq-".contains(c))) {
+            for (i, c) in "KQkq".chars().enumerate() {
+                board.castling[i] = rights.contains(c) && board.has_castling_pieces(i);
+            }
+            // Only a square the side to move can take on, behind an enemy pawn
+            let row = if color == Color::White { 2 } else { 5 };
+            board.en_passant = fields.next().and_then(coords_to_square).filter(|&sq| sq >> 4 == row);
+        }
+        Some((board, color))
+    }
+
+    // The move counters are not tracked, so those fields are always "0 1"
+    pub fn to_fen(&self, color: Color) -> String {
+        let mut fen = String::new();
+        for row in 0u8..8 {
+            let mut empty = 0;
+            for file in 0u8..8 {
+                match self.get_piece(row * 16 + file) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row < 7 {
+                fen.push('/');
+            }
+        }
+        fen.push_str(if color == Color::White { " w " } else { " b " });
+        let rights: String = "KQkq".chars().zip(self.castling).filter(|&(_, right)| right).map(|(c, _)| c).collect();
+        fen.push_str(if rights.is_empty() { "-" } else { &rights });
+        fen.push(' ');
+        fen.push_str(&self.en_passant.and_then(square_to_coords).unwrap_or("-".to_string()));
+        fen.push_str(" 0 1");
+        fen
+    }
+
+    // Whether the king and rook of a castling right are on their squares
+    fn has_castling_pieces(&self, right: usize) -> bool {
+        let (king, _, rook, _) = CASTLING_MOVES[right];
+        let color = if right < 2 { Color::White } else { Color::Black };
+        self.get_piece(king) == Some(Piece { color, kind: PieceType::King })
+            && self.get_piece(rook) == Some(Piece { color, kind: PieceType::Rook })
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        (0u8..128).find(|&sq| {
+            matches!(self.get_piece(sq), Some(Piece { kind: PieceType::King, color: c }) if c == color)
+        })
+    }
+
+    // Plays a move given by its origin and destination. A king moving two
+    // squares castles, a pawn moving diagonally to the en passant square
+    // takes the pawn behind it, and a pawn reaching the last rank promotes
+    // to a queen, the only piece the engine promotes to.
+    pub fn make_move(&mut self, from: Square, to: Square) -> bool {
+        self.make_move_with_network(nnue::active(), from, to)
+    }
+
+    // `make_move` keeping the accumulator for `net`, which need not be the
+    // active network
+    fn make_move_with_network(&mut self, net: Option<&'static Network>, from: Square, to: Square) -> bool {
+        if !Self::is_valid(from) || !Self::is_valid(to) {
+            return false;
+        }
+        let Some(piece) = self.get_piece(from) else { return false };
+
+        let mut placed = piece;
+        let mut captured = self.squares[to as usize].map(|p| (p, to));
+        let mut rook = None;
+        match piece.kind {
+            PieceType::King if from.abs_diff(to) == 2 => {
+                rook = CASTLING_MOVES.iter().find(|c| c.0 == from && c.1 == to).map(|c| (c.2, c.3));
+            }
+            PieceType::Pawn => {
+                if captured.is_none() && from & 7 != to & 7 && self.en_passant == Some(to) {
+                    let taken = if piece.color == Color::White { to + 16 } else { to - 16 };
+                    captured = self.squares[taken as usize].map(|p| (p, taken));
+                    self.squares[taken as usize] = None;
+                }
+                if matches!(to >> 4, 0 | 7) {
+                    placed = Piece { color: piece.color, kind: PieceType::Queen };
+                }
+            }
+            _ => {}
+        }
+        self.squares[from as usize] = None;
+        self.squares[to as usize] = Some(placed);
+        let rook = rook.and_then(|(rook_from, rook_to)| {
+            let rook = self.squares[rook_from as usize].take()?;
+            self.squares[rook_to as usize] = Some(rook);
+            Some((rook, rook_from, rook_to))
+        });
+
+        // A right is lost when the king or that rook moves, or the rook is taken
+        for (i, &(king_from, _, rook_from, _)) in CASTLING_MOVES.iter().enumerate() {
+            if from == king_from || from == rook_from || to == rook_from {
+                self.castling[i] = false;
+            }
+        }
+        self.en_passant = None;
+        if piece.kind == PieceType::Pawn && from.abs_diff(to) == 32 {
+            let enemy_pawn = Some(Piece { color: piece.color.opposite(), kind: PieceType::Pawn });
+            if self.get_piece(to.wrapping_sub(1)) == enemy_pawn || self.get_piece(to + 1) == enemy_pawn {
+                self.en_passant = Some((from + to) / 2);
+            }
+        }
+
+        let removed = [Some((piece, from)), captured, rook.map(|(p, sq, _)| (p, sq))];
+        let added = [Some((placed, to)), rook.map(|(p, _, sq)| (p, sq))];
+        self.update_accumulator(net, &removed, &added);
+        true
+    }
+
+    // A clone copies the parent's accumulator, so make_move only updates the
+    // changed features. Boards without one (or with one for another network)
+    // get a full refresh.
+    fn update_accumulator(
+        &mut self,
+        net: Option<&'static Network>,
+        removed: &[Option<(Piece, Square)>],
+        added: &[Option<(Piece, Square)>],
+    ) {
+        let Some(net) = net else {
+            self.accumulator = None;
+            return;
+        };
+        match &mut self.accumulator {
+            Some(acc) if acc.is_for(net) => {
+                for &(piece, sq) in removed.iter().flatten() {
+                    acc.remove(piece, sq);
+                }
+                for &(piece, sq) in added.iter().flatten() {
+                    acc.add(piece, sq);
+                }
+            }
+            _ => self.accumulator = Some(Box::new(Accumulator::new(net, self))),
+        }
+    }
+
+    pub fn print(&self) {
+        for rank in 0..8 {
+            print!("{} ", 8 - rank);
+            for file in 0..8 {
+                let sq = rank * 16 + file;
+                match self.get_piece(sq) {
+                    Some(p) => print!("{} ", piece_char(p)),
+                    None => print!(". "),
+                }
+            }
+            println!();
+        }
+        println!("  a b c d e f g h");
+    }
+
+    pub fn print_with_highlights(&self, highlights: &[Square]) {
+        for rank in (0..8).rev() {
+            print!("{} ", rank + 1);
+            for file in 0..8 {
+                let sq = rank * 16 + file;
+                if highlights.contains(&sq) {
+                    print!("* ");
+                } else {
+                    match self.get_piece(sq) {
+                        Some(p) => print!("{} ", piece_char(p)),
+                        None => print!(". "),
+                    }
+                }
+            }
+            println!();
+        }
+        println!("  a b c d e f g h");
+    }
+
+    pub fn setup_starting_position(&mut self) {
+        use Color::*;
+        use PieceType::*;
+
+        self.castling = [true; 4];
+
+        let white_back = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
+        for (i, &kind) in white_back.iter().enumerate() {
+            self.set_piece(0x70 + i as u8, Piece { color: White, kind });
+            self.set_piece(
+                0x60 + i as u8,
+                Piece {
+                    color: White,
+                    kind: Pawn,
+                },
+            );
+        }
+
+        let black_back = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
+        for (i, &kind) in black_back.iter().enumerate() {
+            self.set_piece(i as u8, Piece { color: Black, kind });
+            self.set_piece(
+                0x10 + i as u8,
+                Piece {
+                    color: Black,
+                    kind: Pawn,
+                },
+            );
+        }
+    }
+
+    pub fn generate_moves_for_square(&self, square: Square) -> Vec<Square> {
+        self.generate_legal_moves_for_piece(square)
+    }
+
+    pub fn generate_pseudo_moves_for_piece(&self, square: Square) -> Vec<Square> {
+        match self.get_piece(square) {
+            Some(Piece { color, kind }) => match kind {
+                PieceType::Knight => generate_knight_moves(self, square),
+                PieceType::Pawn => generate_pawn_moves(self, square, color),
+                PieceType::King => generate_king_moves(self, square, color),
+                PieceType::Rook => generate_rook_moves(self, square, color),
+                PieceType::Bishop => generate_bishop_moves(self, square, color),
+                PieceType::Queen => generate_queen_moves(self, square, color),
+            },
+            None => vec![],
+        }
+    }
+
+    pub fn generate_legal_moves_for_piece(&self, from: Square) -> Vec<Square> {
+        let mut legal_moves = Vec::new();
+        let pseudo_moves = self.generate_pseudo_moves_for_piece(from);
+
+        for &to in &pseudo_moves {
+            // Check if destination square has a piece of the same color
+            if let Some(target_piece) = self.get_piece(to)
+                && let Some(moving_piece) = self.get_piece(from)
+                && target_piece.color == moving_piece.color
+            {
+                continue; // Skip if trying to capture own piece
+            }
+
+            let mut cloned = self.clone();
+            cloned.make_move(from, to);
+            if let Some(piece) = self.get_piece(from)
+                && !is_in_check(&cloned, piece.color)
+            {
+                legal_moves.push(to);
+            }
+        }
+        if self.get_piece(from).is_some_and(|p| p.kind == PieceType::King) {
+            legal_moves.extend(self.castling_moves(from));
+        }
+
+        legal_moves
+    }
+
+    // Castling destinations of the king on `from`: the right is kept, the
+    // squares between king and rook are empty and the king is not in check,
+    // nor passes or lands on an attacked square
+    fn castling_moves(&self, from: Square) -> Vec<Square> {
+        let Some(king) = self.get_piece(from) else { return vec![] };
+        let first = if king.color == Color::White { 0 } else { 2 };
+        let mut moves = Vec::new();
+        for (right, &(king_from, king_to, rook_from, _)) in CASTLING_MOVES.iter().enumerate().skip(first).take(2) {
+            let (low, high) = (king_from.min(rook_from), king_from.max(rook_from));
+            if !self.castling[right]
+                || from != king_from
+                || !self.has_castling_pieces(right)
+                || (low + 1..high).any(|sq| self.get_piece(sq).is_some())
+                || is_in_check(self, king.color)
+            {
+                continue;
+            }
+            let safe = [(king_from + king_to) / 2, king_to].iter().all(|&to| {
+                let mut cloned = self.clone();
+                cloned.make_move(from, to);
+                !is_in_check(&cloned, king.color)
+            });
+            if safe {
+                moves.push(king_to);
+            }
+        }
+        moves
+    }
+}
+
+pub fn has_legal_moves(board: &Board, color: Color) -> bool {
+    (0u8..128).any(|sq| {
+        board.get_piece(sq).is_some_and(|p| p.color == color) && !board.generate_legal_moves_for_piece(sq).is_empty()
+    })
+}
+
+pub fn is_in_check(board: &Board, color: Color) -> bool {
+    let king_square = board.squares.iter().enumerate().find_map(|(i, &piece)| {
+        if let Some(Piece {
+            kind: PieceType::King,
+            color: c,
+        }) = piece
+        {
+            if c == color { Some(i as u8) } else { None }
+        } else {
+            None
+        }
+    });
+
+    let king_sq = match king_square {
+        Some(sq) => sq,
+        None => return false,
+    };
+
+    for sq in 0u8..128 {
+        if !Board::is_valid(sq) {
+            continue;
+        }
+        if let Some(Piece { color: c, .. }) = board.get_piece(sq)
+            && c != color
+        {
+            let attacks = board.generate_pseudo_moves_for_piece(sq);
+            if attacks.contains(&king_sq) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+const KNIGHT_OFFSETS: [i8; 8] = [-33, -31, -18, -14, 14, 18, 31, 33];
+const KING_OFFSETS: [i8; 8] = [-17, -15, -16, -1, 1, 15, 16, 17];
+const BISHOP_DIRECTIONS: [i8; 4] = [-17, -15, 15, 17];
+const ROOK_DIRECTIONS: [i8; 4] = [-16, -1, 1, 16];
+
+pub fn generate_knight_moves(board: &Board, from: Square) -> Vec<Square> {
+    let mut moves = Vec::new();
+    if let Some(piece) = board.get_piece(from) {
+        for &offset in &KNIGHT_OFFSETS {
+            let to = (from as i16 + offset as i16) as u8;
+            if Board::is_valid(to) {
+                match board.get_piece(to) {
+                    Some(target) if target.color == piece.color => {}
+                    _ => moves.push(to),
+                }
+            }
+        }
+    }
+    moves
+}
+
+pub fn generate_king_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
+    let mut moves = Vec::new();
+    for &offset in &KING_OFFSETS {
+        let to = from.wrapping_add(offset as u8);
+        if Board::is_valid(to) {
+            match board.get_piece(to) {
+                Some(p) if p.color == color => {}
+                _ => moves.push(to),
+            }
+        }
+    }
+    moves
+}
+
+pub fn generate_pawn_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
+    let mut moves = Vec::new();
+    let dir = if color == Color::White { -16 } else { 16 }; // Inverted direction
+    let start_rank = if color == Color::White { 6 } else { 1 }; // Fixed starting ranks
+    let rank = from >> 4;
+
+    // Check diagonal captures first, en passant included
+    let en_passant_row = if color == Color::White { 2 } else { 5 };
+    for &offset in &[dir - 1, dir + 1] {
+        let to = (from as i16 + offset) as u8;
+        if !Board::is_valid(to) {
+            continue;
+        }
+        match board.get_piece(to) {
+            Some(target) if target.color != color => moves.push(to),
+            None if board.en_passant == Some(to) && to >> 4 == en_passant_row => moves.push(to),
+            _ => {}
+        }
+    }
+
+    // Then check forward moves
+    let one_step = (from as i16 + dir) as u8;
+    if Board::is_valid(one_step) && board.get_piece(one_step).is_none() {
+        moves.push(one_step);
+        if rank == start_rank {
+            let two_step = (from as i16 + 2 * dir) as u8;
+            if board.get_piece(two_step).is_none() {
+                moves.push(two_step);
+            }
+        }
+    }
+
+    moves
+}
+
+pub fn generate_rook_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
+    let mut moves = Vec::new();
+    let directions = [16, -16, 1, -1]; // up, down, right, left
+
+    for &dir in &directions {
+        let mut current = from;
+        loop {
+            let next = (current as i16 + dir) as u8;
+            if !Board::is_valid(next) {
+                break;
+            }
+            match board.get_piece(next) {
+                Some(piece) if piece.color == color => break,
+                Some(_) => {
+                    moves.push(next);
+                    break;
+                }
+                None => moves.push(next),
+            }
+            current = next;
+        }
+    }
+    moves
+}
+
+pub fn generate_bishop_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
+    let mut moves = Vec::new();
+    let directions = [17, 15, -17, -15]; // up-right, up-left, down-right, down-left
+
+    for &dir in &directions {
+        let mut current = from;
+        loop {
+            let next = (current as i16 + dir) as u8;
+            if !Board::is_valid(next) {
+                break;
+            }
+            match board.get_piece(next) {
+                Some(piece) if piece.color == color => break,
+                Some(_) => {
+                    moves.push(next);
+                    break;
+                }
+                None => moves.push(next),
+            }
+            current = next;
+        }
+    }
+    moves
+}
+
+pub fn generate_queen_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
+    let mut moves = Vec::new();
+    // Queen moves like a rook and bishop combined
+    moves.extend(generate_rook_moves(board, from, color));
+    moves.extend(generate_bishop_moves(board, from, color));
+    moves
+}
+
+fn piece_char(piece: Piece) -> char {
+    use Color::*;
+    use PieceType::*;
+    match (piece.color, piece.kind) {
+        (White, Pawn) => 'P',
+        (White, Knight) => 'N',
+        (White, Bishop) => 'B',
+        (White, Rook) => 'R',
+        (White, Queen) => 'Q',
+        (White, King) => 'K',
+        (Black, Pawn) => 'p',
+        (Black, Knight) => 'n',
+        (Black, Bishop) => 'b',
+        (Black, Rook) => 'r',
+        (Black, Queen) => 'q',
+        (Black, King) => 'k',
+    }
+}
+
+// Coordinate notation, e.g. e2e4
+pub fn format_move((from, to): (Square, Square)) -> String {
+    format!("{}{}", square_to_coords(from).unwrap(), square_to_coords(to).unwrap())
+}
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+    let kind = match c.to_ascii_lowercase() {
+        'p' => PieceType::Pawn,
+        'n' => PieceType::Knight,
+        'b' => PieceType::Bishop,
+        'r' => PieceType::Rook,
+        'q' => PieceType::Queen,
+        'k' => PieceType::King,
+        _ => return None,
+    };
+    Some(Piece { color, kind })
+}
+
+fn square_to_coords(square: Square) -> Option<String> {
+    if Board::is_valid(square) {
+        let file = square & 0x7;
+        let rank = 8 - (square >> 4);
+        Some(format!("{}{}", (b'a' + file) as char, rank))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mate_scores_move_with_the_node() {
+        // Found 2 plies from the root: mate 5 plies from the root, 3 from the node
+        let stored = score_to_tt(MATE - 5, 2);
+        assert_eq!(stored, MATE - 3);
+        // Reached again 4 plies from the root
+        assert_eq!(score_from_tt(stored, 4), MATE - 7);
+        assert_eq!(score_from_tt(score_to_tt(-MATE + 6, 3), 3), -MATE + 6);
+    }
+
+    #[test]
+    fn ordinary_scores_are_stored_as_they_are() {
+        for score in [0, 35, -250, endgame::KNOWN_WIN + 200] {
+            assert_eq!(score_to_tt(score, 7), score);
+            assert_eq!(score_from_tt(score, 7), score);
+        }
+    }
+
+    #[test]
+    fn mate_in_one_scores_one_ply() {
+        let (board, color) = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let tt = TranspositionTable::new(1);
+        let shared = SearchShared::new(&SearchLimits { depth: 3, ponder: false, infinite: false, nodes: None, movetime: None });
+        let lines = search_multipv(&board, color, 1, &tt, &shared, None);
+        let best = &lines.last().unwrap()[0];
+        assert_eq!(best.best_move, (0x70, 0x00));
+        assert!(is_mate_score(best.score));
+        assert_eq!(mate_plies(best.score), 1);
+    }
+}
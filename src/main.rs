@@ -1,5 +1,25 @@
 // src/main.rs
+use rust_chess_engine::async_search::SearchLimits;
+use rust_chess_engine::bitbase;
+use rust_chess_engine::book::Book;
+use rust_chess_engine::dtm;
+use rust_chess_engine::nnue;
+use rust_chess_engine::params::{self, EvalParams};
+use rust_chess_engine::pgn::{self, Game};
+use rust_chess_engine::san;
+use rust_chess_engine::skill::{Skill, MAX_ELO, MAX_SKILL, MIN_ELO};
+use rust_chess_engine::syzygy;
+use rust_chess_engine::{
+    coords_to_square, engine_make_move, eval, has_legal_moves, is_decisive_score, is_in_check, is_mate_score,
+    mate_plies, search_multipv, uci, Board, Color, Engine, Ponder, SearchShared, Square, MAX_DEPTH,
+};
 use std::io::{self, Write};
+use std::sync::Arc;
+
+// Names in the White and Black tags of the games played here
+const ENGINE_NAME: &str = "Rust Chess Engine";
+const PLAYER_NAME: &str = "Player";
+
 //read input
 fn read_input(prompt: &str) -> String {
     print!("{}", prompt);
@@ -9,717 +29,487 @@ fn read_input(prompt: &str) -> String {
     input.trim().to_string()
 }
 
-fn coords_to_square(coord: &str) -> Option<Square> {
-    if coord.len() != 2 {
-        return None;
-    }
-    let chars: Vec<char> = coord.chars().collect();
-    let file = chars[0].to_ascii_lowercase() as u8;
-    let rank = chars[1] as u8;
-
-    if file < b'a' || file > b'h' || rank < b'1' || rank > b'8' {
-        return None;
-    }
-
-    let file_idx = file - b'a';
-    let rank_idx = 7 - (rank - b'1'); // Invert the rank to match 0x88 board representation
-    Some(rank_idx * 16 + file_idx)
+struct GameOptions {
+    start: Option<(Board, Color)>,
+    pgn_path: String,
 }
 
-const MAX_DEPTH: i32 = 4;
-
-// Add piece-square tables for positional evaluation
-const PAWN_PST: [i32; 64] = [
-    0,  0,  0,  0,  0,  0,  0,  0,
-    50, 50, 50, 50, 50, 50, 50, 50,
-    10, 10, 20, 30, 30, 20, 10, 10,
-    5,  5, 10, 25, 25, 10,  5,  5,
-    0,  0,  0, 20, 20,  0,  0,  0,
-    5, -5,-10,  0,  0,-10, -5,  5,
-    5, 10, 10,-20,-20, 10, 10,  5,
-    0,  0,  0,  0,  0,  0,  0,  0
-];
-
-const KNIGHT_PST: [i32; 64] = [
-    -50,-40,-30,-30,-30,-30,-40,-50,
-    -40,-20,  0,  0,  0,  0,-20,-40,
-    -30,  0, 10, 15, 15, 10,  0,-30,
-    -30,  5, 15, 20, 20, 15,  5,-30,
-    -30,  0, 15, 20, 20, 15,  0,-30,
-    -30,  5, 10, 15, 15, 10,  5,-30,
-    -40,-20,  0,  5,  5,  0,-20,-40,
-    -50,-40,-30,-30,-30,-30,-40,-50
-];
-
-const BISHOP_PST: [i32; 64] = [
-    -20,-10,-10,-10,-10,-10,-10,-20,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-    -10,  0,  5, 10, 10,  5,  0,-10,
-    -10,  5,  5, 10, 10,  5,  5,-10,
-    -10,  0, 10, 10, 10, 10,  0,-10,
-    -10, 10, 10, 10, 10, 10, 10,-10,
-    -10,  5,  0,  0,  0,  0,  5,-10,
-    -20,-10,-10,-10,-10,-10,-10,-20
-];
-
-const ROOK_PST: [i32; 64] = [
-    0,  0,  0,  0,  0,  0,  0,  0,
-    5, 10, 10, 10, 10, 10, 10,  5,
-    -5,  0,  0,  0,  0,  0,  0, -5,
-    -5,  0,  0,  0,  0,  0,  0, -5,
-    -5,  0,  0,  0,  0,  0,  0, -5,
-    -5,  0,  0,  0,  0,  0,  0, -5,
-    -5,  0,  0,  0,  0,  0,  0, -5,
-    0,  0,  0,  5,  5,  0,  0,  0
-];
-
-const QUEEN_PST: [i32; 64] = [
-    -20,-10,-10, -5, -5,-10,-10,-20,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-    -10,  0,  5,  5,  5,  5,  0,-10,
-    -5,  0,  5,  5,  5,  5,  0, -5,
-    0,  0,  5,  5,  5,  5,  0, -5,
-    -10,  5,  5,  5,  5,  5,  0,-10,
-    -10,  0,  5,  0,  0,  0,  0,-10,
-    -20,-10,-10, -5, -5,-10,-10,-20
-];
-
-const KING_PST: [i32; 64] = [
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -20,-30,-30,-40,-40,-30,-30,-20,
-    -10,-20,-20,-20,-20,-20,-20,-10,
-    20, 20,  0,  0,  0,  0, 20, 20,
-    20, 30, 10,  0,  0, 10, 30, 20
-];
-
-// Add piece values for MVV-LVA ordering
-const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20000]; // Pawn, Knight, Bishop, Rook, Queen, King
-
-// Add MVV-LVA table for move ordering
-const MVV_LVA: [[i32; 6]; 6] = [
-    [105, 205, 305, 405, 505, 605], // Pawn captures
-    [104, 204, 304, 404, 504, 604], // Knight captures
-    [103, 203, 303, 403, 503, 603], // Bishop captures
-    [102, 202, 302, 402, 502, 602], // Rook captures
-    [101, 201, 301, 401, 501, 601], // Queen captures
-    [100, 200, 300, 400, 500, 600], // King captures
-];
-
-fn get_piece_value(piece_type: PieceType) -> i32 {
-    match piece_type {
-        PieceType::Pawn => PIECE_VALUES[0],
-        PieceType::Knight => PIECE_VALUES[1],
-        PieceType::Bishop => PIECE_VALUES[2],
-        PieceType::Rook => PIECE_VALUES[3],
-        PieceType::Queen => PIECE_VALUES[4],
-        PieceType::King => PIECE_VALUES[5],
+// Command line options, applied in order:
+//   --params <file>       load evaluation parameters from a JSON file
+//   --save-params <file>  write the current evaluation parameters and exit
+//   --nnue <file>         load an NNUE network and evaluate with it
+//   --syzygy <dirs>       probe Syzygy tablebases from these directories
+//   --dtm <dirs>          probe the engine's own DTM tables from these directories
+//   --book <file>         play from a Polyglot opening book
+//   --fen <fen>           start the game from this position
+//   --pgn <file>          append the game to this file on exit (default games.pgn)
+fn apply_args(engine: &mut Engine, options: &mut GameOptions) -> bool {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut keep_running = true;
+    let mut i = 0;
+    while i < args.len() {
+        let path = args.get(i + 1);
+        match (args[i].as_str(), path) {
+            ("--params", Some(path)) => match EvalParams::load(path) {
+                Ok(p) => params::set(p),
+                Err(e) => {
+                    eprintln!("Cannot load evaluation parameters from {}: {}", path, e);
+                    return false;
+                }
+            },
+            ("--nnue", Some(path)) => match nnue::load(path) {
+                Ok(()) => nnue::set_enabled(true),
+                Err(e) => {
+                    eprintln!("Cannot load network from {}: {}", path, e);
+                    return false;
+                }
+            },
+            ("--syzygy", Some(paths)) => {
+                println!("Found {} tablebases.", syzygy::init(paths));
+            }
+            ("--dtm", Some(paths)) => {
+                println!("Found {} DTM tables.", dtm::init(paths));
+            }
+            ("--book", Some(path)) => match Book::load(path) {
+                Ok(book) => engine.book = Some(Arc::new(book)),
+                Err(e) => {
+                    eprintln!("Cannot load book from {}: {}", path, e);
+                    return false;
+                }
+            },
+            ("--fen", Some(fen)) => match Board::from_fen(fen) {
+                Some(start) => options.start = Some(start),
+                None => {
+                    eprintln!("Invalid FEN: {}", fen);
+                    return false;
+                }
+            },
+            ("--pgn", Some(path)) => options.pgn_path = path.clone(),
+            ("--save-params", Some(path)) => {
+                if let Err(e) = params::get().save(path) {
+                    eprintln!("Cannot save evaluation parameters to {}: {}", path, e);
+                }
+                keep_running = false;
+            }
+            (arg, _) => {
+                eprintln!("Unknown or incomplete option: {}", arg);
+                return false;
+            }
+        }
+        i += 2;
     }
+    keep_running
 }
 
-fn get_mvv_lva_score(attacker: PieceType, victim: PieceType) -> i32 {
-    let attacker_idx = match attacker {
-        PieceType::Pawn => 0,
-        PieceType::Knight => 1,
-        PieceType::Bishop => 2,
-        PieceType::Rook => 3,
-        PieceType::Queen => 4,
-        PieceType::King => 5,
-    };
-    let victim_idx = match victim {
-        PieceType::Pawn => 0,
-        PieceType::Knight => 1,
-        PieceType::Bishop => 2,
-        PieceType::Rook => 3,
-        PieceType::Queen => 4,
-        PieceType::King => 5,
-    };
-    MVV_LVA[attacker_idx][victim_idx]
+// A move given as origin and destination square, e.g. "e2 e4"
+fn read_coordinates(board: &Board, color: Color, from: &str, to: &str) -> Result<(Square, Square), &'static str> {
+    let from = coords_to_square(from).ok_or("Invalid source square.")?;
+    let to = coords_to_square(to).ok_or("Invalid destination square.")?;
+    match board.get_piece(from) {
+        Some(piece) if piece.color != color => Err("That's not your piece."),
+        Some(_) if board.generate_moves_for_square(from).contains(&to) => Ok((from, to)),
+        Some(_) => Err("Illegal move."),
+        None => Err("No piece on source square."),
+    }
 }
 
-fn get_pst_value(square: Square, piece_type: PieceType, color: Color) -> i32 {
-    let file = square & 0x7;
-    let rank = square >> 4;
-    let idx = if color == Color::White {
-        rank * 8 + file
+// The engine's evaluation as a PGN comment: score in pawns for the engine
+// and search depth, e.g. "+0.35/4", "+M3/4" for a mate in 3 moves and
+// "+TB/4" for a tablebase win
+fn eval_comment((score, depth): (i32, i32)) -> String {
+    let sign = if score > 0 { "+" } else { "-" };
+    if is_mate_score(score) {
+        format!("{}M{}/{}", sign, (mate_plies(score) + 1) / 2, depth)
+    } else if is_decisive_score(score) {
+        format!("{}TB/{}", sign, depth)
     } else {
-        (7 - rank) * 8 + file
-    } as usize;
-
-    match piece_type {
-        PieceType::Pawn => PAWN_PST[idx],
-        PieceType::Knight => KNIGHT_PST[idx],
-        PieceType::Bishop => BISHOP_PST[idx],
-        PieceType::Rook => ROOK_PST[idx],
-        PieceType::Queen => QUEEN_PST[idx],
-        PieceType::King => KING_PST[idx],
+        format!("{:+.2}/{}", score as f64 / 100.0, depth)
     }
 }
 
-// Move struct for move ordering
-#[derive(Clone, Copy)]
-struct Move {
-    from: Square,
-    to: Square,
-    score: i32,
-}
-
-fn evaluate(board: &Board, color: Color) -> i32 {
-    let mut score = 0;
-
-    for sq in 0u8..128 {
-        if !Board::is_valid(sq) {
-            continue;
-        }
-        if let Some(piece) = board.get_piece(sq) {
-            let value = get_piece_value(piece.kind);
-            let piece_value = if piece.color == color { value } else { -value };
-            let pst_value = if piece.color == color {
-                get_pst_value(sq, piece.kind, color)
-            } else {
-                -get_pst_value(sq, piece.kind, if color == Color::White { Color::Black } else { Color::White })
-            };
-            score += piece_value + pst_value;
-        }
+// Ends the game if `color` has no legal move; checkmate or stalemate
+fn game_over(board: &Board, color: Color, game: &mut Game) -> bool {
+    if has_legal_moves(board, color) {
+        return false;
     }
-
-    score
-}
-
-fn get_moves_with_scores(board: &Board, color: Color) -> Vec<Move> {
-    let mut moves = Vec::new();
-    
-    for from in 0u8..128 {
-        if !Board::is_valid(from) {
-            continue;
-        }
-        if let Some(piece) = board.get_piece(from) {
-            if piece.color == color {
-                let legal_moves = board.generate_legal_moves_for_piece(from);
-                for &to in &legal_moves {
-                    let mut clone = board.clone();
-                    clone.make_move(from, to);
-                    if !is_in_check(&clone, color) {
-                        let mut score = evaluate(&clone, color);
-                        
-                        // Add MVV-LVA score for captures
-                        if let Some(captured_piece) = board.get_piece(to) {
-                            if captured_piece.color != color {
-                                score += get_mvv_lva_score(piece.kind, captured_piece.kind);
-                            }
-                        }
-                        
-                        moves.push(Move { from, to, score });
-                    }
-                }
-            }
-        }
+    if is_in_check(board, color) {
+        println!("Checkmate.");
+        game.set_result(if color == Color::White { "0-1" } else { "1-0" });
+    } else {
+        println!("Stalemate.");
+        game.set_result("1/2-1/2");
     }
-    
-    // Sort moves by score in descending order
-    moves.sort_by(|a, b| b.score.cmp(&a.score));
-    moves
+    true
 }
 
-fn negamax(board: &Board, depth: i32, mut alpha: i32, beta: i32, color: Color) -> i32 {
-    if depth == 0 {
-        return evaluate(board, color);
-    }
-
-    let mut best_score = -i32::MAX;
-    let mut has_legal_move = false;
-
-    let moves = get_moves_with_scores(board, color);
-    for mv in moves {
-        let mut clone = board.clone();
-        clone.make_move(mv.from, mv.to);
-        if !is_in_check(&clone, color) {
-            has_legal_move = true;
-            let score = -negamax(&clone, depth - 1, -beta, -alpha, if color == Color::White { Color::Black } else { Color::White });
-            best_score = best_score.max(score);
-            alpha = alpha.max(score);
-            if alpha >= beta {
-                return alpha; // Beta cutoff
-            }
-        }
-    }
-
-    if !has_legal_move {
-        if is_in_check(board, color) {
-            return -i32::MAX + 1; // Checkmate
-        }
-        return 0; // Stalemate
-    }
-
-    best_score
+// The player of `color` in the game's tags
+fn set_player(game: &mut Game, color: Color, name: &str) {
+    game.set_tag(if color == Color::White { "White" } else { "Black" }, name);
 }
 
-fn find_best_move(board: &Board, color: Color) -> Option<(Square, Square)> {
-    let mut best_score = -i32::MAX;
-    let mut best_move = None;
-    let mut alpha = -i32::MAX;
-    let beta = i32::MAX;
-
-    // Iterative deepening
-    for depth in 1..=MAX_DEPTH {
-        let moves = get_moves_with_scores(board, color);
-        for mv in moves {
-            let mut clone = board.clone();
-            clone.make_move(mv.from, mv.to);
-            if !is_in_check(&clone, color) {
-                let score = -negamax(&clone, depth - 1, -beta, -alpha, if color == Color::White { Color::Black } else { Color::White });
-                if score > best_score {
-                    best_score = score;
-                    best_move = Some((mv.from, mv.to));
-                }
-                alpha = alpha.max(score);
-            }
-        }
+// The engine plays the side to move and its move is recorded; false once
+// the game is over
+fn engine_turn(engine: &Engine, game: &mut Game, ponder: Option<Ponder>) -> bool {
+    let (board, engine_color) = game.position();
+    let mut board = board.clone();
+    if game_over(&board, engine_color, game) {
+        return false;
     }
-
-    best_move
+    let Some(played) = engine_make_move(&mut board, engine_color, engine, ponder) else {
+        return false;
+    };
+    game.play(played.mv, played.eval.map(eval_comment));
+    set_player(game, engine_color, ENGINE_NAME);
+    !game_over(&board, engine_color.opposite(), game)
 }
 
-fn engine_make_move(board: &mut Board, engine_color: Color) -> bool {
-    if let Some((from, to)) = find_best_move(board, engine_color) {
-        board.make_move(from, to);
-        println!(
-            "Engine plays: {} -> {}",
-            square_to_coords(from).unwrap(),
-            square_to_coords(to).unwrap()
-        );
-        true
+// The move that led to the current position with its comment, and the
+// moves the game goes on with: the main line first, then the variations
+fn show_position(game: &Game) {
+    let node = game.node(game.current());
+    if node.mv.is_some() {
+        let number = (game.ply() + (game.start_color() == Color::Black) as usize).div_ceil(2);
+        let dots = if game.position().1 == Color::Black { "." } else { "..." };
+        let comment = node.comment.as_ref().map_or(String::new(), |c| format!(" {{{}}}", c));
+        println!("Move {}{} {}{}", number, dots, node.san, comment);
     } else {
-        println!("Engine has no legal moves.");
-        false
+        println!("Start of the game.");
+    }
+    if !node.children.is_empty() {
+        let next: Vec<String> =
+            node.children.iter().enumerate().map(|(i, &c)| format!("{}) {}", i + 1, game.node(c).san)).collect();
+        println!("Next: {}", next.join("  "));
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum Color {
-    White,
-    Black,
-}
-
-#[derive(Copy, Clone, PartialEq)]
-pub enum PieceType {
-    Pawn,
-    Knight,
-    Bishop,
-    Rook,
-    Queen,
-    King,
-}
-
-#[derive(Copy, Clone)]
-pub struct Piece {
-    pub color: Color,
-    pub kind: PieceType,
+// Reads game `number` (from 1) of a PGN file
+fn load_game(path: &str, number: usize) -> Result<Game, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    let mut games = pgn::parse(&text);
+    let count = games.len();
+    if number == 0 || number > count {
+        return Err(format!("{} has {} game(s).", path, count));
+    }
+    games.swap_remove(number - 1).map_err(|e| format!("{}, game {}: {}", path, number, e))
 }
 
-pub type Square = u8; // 0..127
-
-#[derive(Clone)]
-pub struct Board {
-    pub squares: [Option<Piece>; 128], // 0x88 board
+const HELP: &str = "\
+Moves: e4, Nf3, exd5 (algebraic) or e2 e4 (from and to square); you play the side to move
+  go                    let the engine play the side to move
+  analyze [n]           show the best n moves (default 3)
+  eval                  evaluation breakdown of the position
+  load <file> [n]       load game n (default 1) of a PGN file
+  back, forward [n]     step through the game; forward takes the main line or variation n
+  start, end            go to the start or the end of the main line
+  pgn                   print the game
+  save [file]           write the game as PGN (default game.pgn)
+  comments on|off       engine scores as comments in saved games
+  nnue on|off, skill <0-20>, elo <n>, threads <n>, ponder on|off, book best|random|off
+  uci, exit";
+
+// Keeps the game played in this session
+fn append_game(game: &Game, path: &str, comments: bool) {
+    match game.save(path, true, comments) {
+        Ok(()) => println!("Game saved to {}.", path),
+        Err(e) => eprintln!("Cannot save the game to {}: {}", path, e),
+    }
 }
 
-impl Board {
-    pub fn new() -> Self {
-        Self {
-            squares: [None; 128],
-        }
+fn main() {
+    let mut engine = Engine::new();
+    let mut options = GameOptions { start: None, pgn_path: "games.pgn".to_string() };
+    if !apply_args(&mut engine, &mut options) {
+        return;
     }
+    bitbase::init();
 
-    pub fn is_valid(square: Square) -> bool {
-        square & 0x88 == 0
-    }
+    let (board, start_color) = options.start.unwrap_or_else(|| {
+        let mut board = Board::new();
+        board.setup_starting_position();
+        (board, Color::White)
+    });
 
-    pub fn set_piece(&mut self, square: Square, piece: Piece) {
-        if Self::is_valid(square) {
-            self.squares[square as usize] = Some(piece);
+    let mut pondering: Option<Ponder> = None;
+    let mut game = Game::new(&board, start_color);
+    game.set_tag("Event", "Casual game");
+    game.set_tag("TimeControl", "-");
+    let mut comments = true;
+    // Whether a move was played here, rather than only read from a file
+    let mut played = false;
+
+    if start_color == Color::Black {
+        played = true;
+        if !engine_turn(&engine, &mut game, None) {
+            game.position().0.print();
+            append_game(&game, &options.pgn_path, comments);
+            return;
         }
-    }
-
-    pub fn get_piece(&self, square: Square) -> Option<Piece> {
-        if Self::is_valid(square) {
-            self.squares[square as usize]
-        } else {
-            None
+        if engine.ponder {
+            pondering = Ponder::start(game.position().0, Color::Black, &engine);
         }
     }
 
-    pub fn make_move(&mut self, from: Square, to: Square) -> bool {
-        if !Self::is_valid(from) || !Self::is_valid(to) {
-            return false;
-        }
-
-        if let Some(piece) = self.get_piece(from) {
-            self.squares[to as usize] = Some(piece);
-            self.squares[from as usize] = None;
-            true
-        } else {
-            false
-        }
-    }
+    loop {
+        let (board, to_move) = game.position();
+        let (board, to_move) = (board.clone(), to_move);
+        board.print();
 
-    pub fn print(&self) {
-        for rank in 0..8 {
-            print!("{} ", 8 - rank);
-            for file in 0..8 {
-                let sq = rank * 16 + file;
-                match self.get_piece(sq) {
-                    Some(p) => print!("{} ", piece_char(p)),
-                    None => print!(". "),
-                }
+        let input = read_input("\nEnter your move (e.g., e4, Nf3 or e2 e4) or a command ('help' for a list): ");
+        if input == "exit" {
+            if let Some(ponder) = pondering.take() {
+                ponder.stop();
             }
-            println!();
+            break;
         }
-        println!("  a b c d e f g h");
-    }
-
-    pub fn print_with_highlights(&self, highlights: &[Square]) {
-        for rank in (0..8).rev() {
-            print!("{} ", rank + 1);
-            for file in 0..8 {
-                let sq = rank * 16 + file;
-                if highlights.contains(&sq) {
-                    print!("* ");
-                } else {
-                    match self.get_piece(sq) {
-                        Some(p) => print!("{} ", piece_char(p)),
-                        None => print!(". "),
-                    }
-                }
+        if input == "uci" {
+            if let Some(ponder) = pondering.take() {
+                ponder.stop();
             }
-            println!();
-        }
-        println!("  a b c d e f g h");
-    }
-
-    pub fn setup_starting_position(&mut self) {
-        use Color::*;
-        use PieceType::*;
-
-        let white_back = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
-        for (i, &kind) in white_back.iter().enumerate() {
-            self.set_piece(0x70 + i as u8, Piece { color: White, kind });
-            self.set_piece(
-                0x60 + i as u8,
-                Piece {
-                    color: White,
-                    kind: Pawn,
-                },
-            );
+            if played {
+                append_game(&game, &options.pgn_path, comments);
+            }
+            uci::run(&mut engine);
+            return;
         }
-
-        let black_back = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
-        for (i, &kind) in black_back.iter().enumerate() {
-            self.set_piece(0x00 + i as u8, Piece { color: Black, kind });
-            self.set_piece(
-                0x10 + i as u8,
-                Piece {
-                    color: Black,
-                    kind: Pawn,
-                },
-            );
+        if input == "help" {
+            println!("{}", HELP);
+            continue;
         }
-    }
 
-    pub fn generate_moves_for_square(&self, square: Square) -> Vec<Square> {
-        self.generate_legal_moves_for_piece(square)
-    }
-
-    pub fn generate_pseudo_moves_for_piece(&self, square: Square) -> Vec<Square> {
-        match self.get_piece(square) {
-            Some(Piece { color, kind }) => match kind {
-                PieceType::Knight => generate_knight_moves(self, square),
-                PieceType::Pawn => generate_pawn_moves(self, square, color),
-                PieceType::King => generate_king_moves(self, square, color),
-                PieceType::Rook => generate_rook_moves(self, square, color),
-                PieceType::Bishop => generate_bishop_moves(self, square, color),
-                PieceType::Queen => generate_queen_moves(self, square, color),
-            },
-            None => vec![],
+        let parts: Vec<&str> = input.split_whitespace().collect();
+        let command = parts.first().copied();
+        if matches!(command, Some("load" | "back" | "forward" | "start" | "end" | "go"))
+            && let Some(ponder) = pondering.take()
+        {
+            ponder.stop();
         }
-    }
-
-    pub fn generate_legal_moves_for_piece(&self, from: Square) -> Vec<Square> {
-        let mut legal_moves = Vec::new();
-        let pseudo_moves = self.generate_pseudo_moves_for_piece(from);
-
-        for &to in &pseudo_moves {
-            // Check if destination square has a piece of the same color
-            if let Some(target_piece) = self.get_piece(to) {
-                if let Some(moving_piece) = self.get_piece(from) {
-                    if target_piece.color == moving_piece.color {
-                        continue; // Skip if trying to capture own piece
+        match command {
+            Some("load") => {
+                let number = match parts.get(2).map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) => n,
+                    None => 1,
+                    Some(Err(_)) => {
+                        println!("Usage: load <file> [n]");
+                        continue;
+                    }
+                };
+                let Some(path) = parts.get(1) else {
+                    println!("Usage: load <file> [n]");
+                    continue;
+                };
+                match load_game(path, number) {
+                    Ok(loaded) => {
+                        game = loaded;
+                        played = false;
+                        println!(
+                            "{} - {} {}",
+                            game.tag("White").unwrap_or("?"),
+                            game.tag("Black").unwrap_or("?"),
+                            game.tag("Result").unwrap_or("*")
+                        );
+                        show_position(&game);
                     }
+                    Err(message) => println!("{}", message),
                 }
+                continue;
             }
-
-            let mut cloned = self.clone();
-            cloned.make_move(from, to);
-            if let Some(piece) = self.get_piece(from) {
-                if !is_in_check(&cloned, piece.color) {
-                    legal_moves.push(to);
+            Some("back") => {
+                if game.back() {
+                    show_position(&game);
+                } else {
+                    println!("Already at the start.");
                 }
+                continue;
             }
-        }
-
-        legal_moves
-    }
-}
-
-pub fn is_in_check(board: &Board, color: Color) -> bool {
-    let king_square = board.squares.iter().enumerate().find_map(|(i, &piece)| {
-        if let Some(Piece {
-            kind: PieceType::King,
-            color: c,
-        }) = piece
-        {
-            if c == color { Some(i as u8) } else { None }
-        } else {
-            None
-        }
-    });
-
-    let king_sq = match king_square {
-        Some(sq) => sq,
-        None => return false,
-    };
-
-    for sq in 0u8..128 {
-        if !Board::is_valid(sq) {
-            continue;
-        }
-        if let Some(Piece { color: c, .. }) = board.get_piece(sq) {
-            if c != color {
-                let attacks = board.generate_pseudo_moves_for_piece(sq);
-                if attacks.contains(&king_sq) {
-                    return true;
+            Some("forward") => {
+                match parts.get(1).map_or(Ok(1), |n| n.parse::<usize>()) {
+                    Ok(n) if n >= 1 && game.forward(n - 1) => show_position(&game),
+                    Ok(n) if n >= 1 => println!("No such move."),
+                    _ => println!("Usage: forward [n]"),
                 }
+                continue;
             }
-        }
-    }
-
-    false
-}
-
-const KNIGHT_OFFSETS: [i8; 8] = [-33, -31, -18, -14, 14, 18, 31, 33];
-const KING_OFFSETS: [i8; 8] = [-17, -15, -16, -1, 1, 15, 16, 17];
-
-pub fn generate_knight_moves(board: &Board, from: Square) -> Vec<Square> {
-    let mut moves = Vec::new();
-    if let Some(piece) = board.get_piece(from) {
-        for &offset in &KNIGHT_OFFSETS {
-            let to = (from as i16 + offset as i16) as u8;
-            if Board::is_valid(to) {
-                match board.get_piece(to) {
-                    Some(target) if target.color == piece.color => {}
-                    _ => moves.push(to),
-                }
+            Some("start") => {
+                game.go_to(0);
+                show_position(&game);
+                continue;
             }
-        }
-    }
-    moves
-}
-
-pub fn generate_king_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
-    let mut moves = Vec::new();
-    for &offset in &KING_OFFSETS {
-        let to = from.wrapping_add(offset as u8);
-        if Board::is_valid(to) {
-            match board.get_piece(to) {
-                Some(p) if p.color == color => {}
-                _ => moves.push(to),
+            Some("end") => {
+                game.go_to_end();
+                show_position(&game);
+                continue;
             }
-        }
-    }
-    moves
-}
-
-pub fn generate_pawn_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
-    let mut moves = Vec::new();
-    let dir = if color == Color::White { -16 } else { 16 }; // Inverted direction
-    let start_rank = if color == Color::White { 6 } else { 1 }; // Fixed starting ranks
-    let rank = from >> 4;
-
-    // Check diagonal captures first
-    for &offset in &[dir - 1, dir + 1] {
-        let to = (from as i16 + offset) as u8;
-        if Board::is_valid(to) {
-            if let Some(target) = board.get_piece(to) {
-                if target.color != color {
-                    moves.push(to);
+            Some("pgn") => {
+                print!("{}", game.to_pgn(comments));
+                continue;
+            }
+            Some("go") => {
+                played = true;
+                if engine_turn(&engine, &mut game, None) && engine.ponder {
+                    pondering = Ponder::start(game.position().0, to_move, &engine);
                 }
+                continue;
             }
-        }
-    }
-
-    // Then check forward moves
-    let one_step = (from as i16 + dir) as u8;
-    if Board::is_valid(one_step) && board.get_piece(one_step).is_none() {
-        moves.push(one_step);
-        if rank == start_rank {
-            let two_step = (from as i16 + 2 * dir) as u8;
-            if board.get_piece(two_step).is_none() {
-                moves.push(two_step);
+            Some("save") => {
+                let path = parts.get(1).unwrap_or(&"game.pgn");
+                match game.save(path, false, comments) {
+                    Ok(()) => println!("Game saved to {}.", path),
+                    Err(e) => println!("Cannot save the game to {}: {}", path, e),
+                }
+                continue;
             }
-        }
-    }
-
-    moves
-}
-
-pub fn generate_rook_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
-    let mut moves = Vec::new();
-    let directions = [16, -16, 1, -1]; // up, down, right, left
-
-    for &dir in &directions {
-        let mut current = from;
-        loop {
-            let next = (current as i16 + dir) as u8;
-            if !Board::is_valid(next) {
-                break;
+            Some("comments") => {
+                match parts.get(1) {
+                    Some(&"on") => comments = true,
+                    Some(&"off") => comments = false,
+                    _ => println!("Usage: comments on|off"),
+                }
+                continue;
             }
-            match board.get_piece(next) {
-                Some(piece) if piece.color == color => break,
-                Some(_) => {
-                    moves.push(next);
-                    break;
+            Some("analyze") => {
+                let num_pv = match parts.get(1) {
+                    Some(n) => match n.parse::<usize>() {
+                        Ok(n) if n >= 1 => n,
+                        _ => {
+                            println!("Usage: analyze [n]");
+                            continue;
+                        }
+                    },
+                    None => 3,
+                };
+                if let Some(ponder) = pondering.take() {
+                    ponder.stop();
                 }
-                None => moves.push(next),
+                let shared = SearchShared::new(&SearchLimits {
+                    depth: MAX_DEPTH,
+                    ponder: false,
+                    infinite: false,
+                    nodes: None,
+                    movetime: None,
+                });
+                for (depth, lines) in search_multipv(&board, to_move, num_pv, &engine.tt, &shared, None).iter().enumerate() {
+                    println!("depth {}", depth + 1);
+                    for (rank, line) in lines.iter().enumerate() {
+                        let pv = san::to_san_line(&board, to_move, &line.pv);
+                        let best = san::to_san(&board, to_move, line.best_move);
+                        println!("  {}. {} ({:+}) {}", rank + 1, best, line.score, pv.join(" "));
+                    }
+                }
+                continue;
             }
-            current = next;
-        }
-    }
-    moves
-}
-
-pub fn generate_bishop_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
-    let mut moves = Vec::new();
-    let directions = [17, 15, -17, -15]; // up-right, up-left, down-right, down-left
-
-    for &dir in &directions {
-        let mut current = from;
-        loop {
-            let next = (current as i16 + dir) as u8;
-            if !Board::is_valid(next) {
-                break;
+            Some("eval") => {
+                println!("{}", eval::trace(&board, to_move));
+                continue;
+            }
+            Some("nnue") => {
+                match parts.get(1) {
+                    Some(&"on") if nnue::loaded() => nnue::set_enabled(true),
+                    Some(&"on") => println!("No network loaded, start with --nnue <file>."),
+                    Some(&"off") => nnue::set_enabled(false),
+                    _ => println!("Usage: nnue on|off"),
+                }
+                continue;
             }
-            match board.get_piece(next) {
-                Some(piece) if piece.color == color => break,
-                Some(_) => {
-                    moves.push(next);
-                    break;
+            Some("skill") => {
+                match parts.get(1).and_then(|n| n.parse::<u32>().ok()) {
+                    Some(level) if level <= MAX_SKILL => {
+                        engine.skill = Skill::new(level);
+                        println!("Skill level {}.", level);
+                    }
+                    _ => println!("Usage: skill <0-{}>", MAX_SKILL),
                 }
-                None => moves.push(next),
+                continue;
             }
-            current = next;
-        }
-    }
-    moves
-}
-
-pub fn generate_queen_moves(board: &Board, from: Square, color: Color) -> Vec<Square> {
-    let mut moves = Vec::new();
-    // Queen moves like a rook and bishop combined
-    moves.extend(generate_rook_moves(board, from, color));
-    moves.extend(generate_bishop_moves(board, from, color));
-    moves
-}
-
-fn piece_char(piece: Piece) -> char {
-    use Color::*;
-    use PieceType::*;
-    match (piece.color, piece.kind) {
-        (White, Pawn) => 'P',
-        (White, Knight) => 'N',
-        (White, Bishop) => 'B',
-        (White, Rook) => 'R',
-        (White, Queen) => 'Q',
-        (White, King) => 'K',
-        (Black, Pawn) => 'p',
-        (Black, Knight) => 'n',
-        (Black, Bishop) => 'b',
-        (Black, Rook) => 'r',
-        (Black, Queen) => 'q',
-        (Black, King) => 'k',
-    }
-}
-
-fn square_to_coords(square: Square) -> Option<String> {
-    if Board::is_valid(square) {
-        let file = (square & 0x7) as u8;
-        let rank = (square >> 4) + 1;
-        Some(format!("{}{}", (b'a' + file) as char, rank))
-    } else {
-        None
-    }
-}
-
-fn main() {
-    let mut board = Board::new();
-    board.setup_starting_position();
-
-    let user_color = Color::White;
-    let engine_color = Color::Black;
-
-    loop {
-        board.print();
-
-        let input = read_input("\nEnter your move (e.g., e2 e4) or 'exit': ");
-        if input == "exit" {
-            break;
-        }
-
-        let parts: Vec<&str> = input.split_whitespace().collect();
-        if parts.len() != 2 {
-            println!("Invalid format. Use: e2 e4");
-            continue;
-        }
-
-        let from = match coords_to_square(parts[0]) {
-            Some(sq) => sq,
-            None => {
-                println!("Invalid source square.");
+            Some("elo") => {
+                match parts.get(1).and_then(|n| n.parse::<u32>().ok()) {
+                    Some(elo) => {
+                        engine.skill = Skill::from_elo(elo);
+                        println!("Playing at about {} Elo (skill level {}).", elo.clamp(MIN_ELO, MAX_ELO), engine.skill.level());
+                    }
+                    None => println!("Usage: elo <{}-{}>", MIN_ELO, MAX_ELO),
+                }
                 continue;
             }
-        };
-
-        let to = match coords_to_square(parts[1]) {
-            Some(sq) => sq,
-            None => {
-                println!("Invalid destination square.");
+            Some("ponder") => {
+                match parts.get(1) {
+                    Some(&"on") => engine.ponder = true,
+                    Some(&"off") => {
+                        engine.ponder = false;
+                        if let Some(ponder) = pondering.take() {
+                            ponder.stop();
+                        }
+                    }
+                    _ => println!("Usage: ponder on|off"),
+                }
                 continue;
             }
-        };
-
-        if let Some(piece) = board.get_piece(from) {
-            if piece.color != user_color {
-                println!("That's not your piece.");
+            Some("book") => {
+                match parts.get(1) {
+                    Some(&"best") => engine.best_book_move = true,
+                    Some(&"random") => engine.best_book_move = false,
+                    Some(&"off") => engine.book = None,
+                    _ => println!("Usage: book best|random|off"),
+                }
                 continue;
             }
-
-            let legal = board.generate_moves_for_square(from);
-            if legal.contains(&to) {
-                board.make_move(from, to);
-            } else {
-                println!("Illegal move.");
+            Some("threads") => {
+                match parts.get(1).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n >= 1 => {
+                        engine.threads = n;
+                        println!("Using {} search thread(s).", n);
+                    }
+                    _ => println!("Usage: threads <n> (n >= 1)"),
+                }
                 continue;
             }
-        } else {
-            println!("No piece on source square.");
-            continue;
+            _ => {}
         }
+        let (from, to) = match parts[..] {
+            [text] => match san::parse(&board, to_move, text) {
+                Ok(mv) => mv,
+                Err(e) => {
+                    println!("{}: {}", text, e);
+                    continue;
+                }
+            },
+            [from, to] => match read_coordinates(&board, to_move, from, to) {
+                Ok(mv) => mv,
+                Err(message) => {
+                    println!("{}", message);
+                    continue;
+                }
+            },
+            _ => {
+                println!("Invalid format. Use: e4, Nf3 or e2 e4");
+                continue;
+            }
+        };
+        game.play((from, to), None);
+        set_player(&mut game, to_move, PLAYER_NAME);
+        played = true;
+
+        // Keep the ponder search only if the user played the move we expected
+        let ponder = match pondering.take() {
+            Some(ponder) if ponder.expected == (from, to) => Some(ponder),
+            Some(ponder) => {
+                ponder.stop();
+                None
+            }
+            None => None,
+        };
 
-        if !engine_make_move(&mut board, engine_color) {
-            println!("Game over. Engine has no legal moves.");
+        if !engine_turn(&engine, &mut game, ponder) {
+            game.position().0.print();
             break;
         }
+
+        if engine.ponder {
+            pondering = Ponder::start(game.position().0, to_move.opposite(), &engine);
+        }
+    }
+
+    if played {
+        append_game(&game, &options.pgn_path, comments);
     }
 }
 
@@ -0,0 +1,264 @@
+// src/nnue.rs
+//
+// Optional neural network evaluation. The network is the simple "768"
+// architecture: one input per (piece colour, piece type, square) seen from
+// each side, a hidden layer of HIDDEN neurons per side with clipped ReLU, and
+// a single output neuron. The hidden layer sums (the accumulators) live in
+// the board and are updated incrementally by `Board::make_move`.
+//
+// Network file, all values little-endian i16:
+//   feature weights  768 x HIDDEN, feature-major, scaled by QA
+//   feature biases   HIDDEN, scaled by QA
+//   output weights   2 x HIDDEN, side to move first, scaled by QB
+//   output bias      1, scaled by QA * QB
+// Feature index = [0 own piece, 384 enemy piece] + piece type * 64 + square,
+// squares numbered a1 = 0 .. h8 = 63 and flipped vertically for Black.
+// Trailing padding (as written by some trainers) is ignored.
+use crate::{Board, Color, Piece, Square};
+use std::fs;
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 256;
+const QA: i32 = 255;
+const QB: i32 = 64;
+// Output scale to centipawns
+const SCALE: i32 = 400;
+
+const FILE_VALUES: usize = INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1;
+
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN]>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+// Networks are loaded rarely and never freed, so accumulators can point to
+// theirs without reference counting
+static NETWORK: AtomicPtr<Network> = AtomicPtr::new(ptr::null_mut());
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn load(path: &str) -> io::Result<()> {
+    let bytes = fs::read(path)?;
+    if bytes.len() < FILE_VALUES * 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected {} bytes for a 768x{} network, found {}", FILE_VALUES * 2, HIDDEN, bytes.len()),
+        ));
+    }
+    let mut values = bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+    let mut next = || values.next().unwrap();
+
+    let mut net = Network {
+        feature_weights: vec![[0; HIDDEN]; INPUTS],
+        feature_bias: [0; HIDDEN],
+        output_weights: [[0; HIDDEN]; 2],
+        output_bias: 0,
+    };
+    for row in net.feature_weights.iter_mut() {
+        row.iter_mut().for_each(|w| *w = next());
+    }
+    net.feature_bias.iter_mut().for_each(|b| *b = next());
+    for row in net.output_weights.iter_mut() {
+        row.iter_mut().for_each(|w| *w = next());
+    }
+    net.output_bias = next();
+
+    NETWORK.store(Box::into_raw(Box::new(net)), Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn loaded() -> bool {
+    !NETWORK.load(Ordering::Relaxed).is_null()
+}
+
+// The network to evaluate with, if one is loaded and selected
+pub fn active() -> Option<&'static Network> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    // SAFETY: the pointer is either null or comes from a leaked Box
+    unsafe { NETWORK.load(Ordering::Acquire).as_ref() }
+}
+
+fn feature(perspective: Color, piece: Piece, sq: Square) -> usize {
+    let row = (sq >> 4) as usize;
+    let file = (sq & 7) as usize;
+    // 0x88 row 0 is rank 8
+    let square = if perspective == Color::White { (7 - row) * 8 + file } else { row * 8 + file };
+    let side = if piece.color == perspective { 0 } else { 384 };
+    side + piece.kind as usize * 64 + square
+}
+
+#[derive(Clone)]
+pub struct Accumulator {
+    net: &'static Network,
+    values: [[i16; HIDDEN]; 2], // indexed by perspective
+}
+
+impl Accumulator {
+    pub fn new(net: &'static Network, board: &Board) -> Self {
+        let mut acc = Accumulator {
+            net,
+            values: [net.feature_bias; 2],
+        };
+        for sq in 0u8..128 {
+            if let Some(piece) = board.get_piece(sq) {
+                acc.add(piece, sq);
+            }
+        }
+        acc
+    }
+
+    pub fn is_for(&self, net: &Network) -> bool {
+        ptr::eq(self.net, net)
+    }
+
+    pub fn add(&mut self, piece: Piece, sq: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = &self.net.feature_weights[feature(perspective, piece, sq)];
+            for (v, w) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *v = v.wrapping_add(*w);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, piece: Piece, sq: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = &self.net.feature_weights[feature(perspective, piece, sq)];
+            for (v, w) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *v = v.wrapping_sub(*w);
+            }
+        }
+    }
+}
+
+// Sum of clipped ReLU(acc) * weights
+fn dot(acc: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 support was just checked
+        return unsafe { dot_avx2(acc, weights) };
+    }
+    dot_scalar(acc, weights)
+}
+
+fn dot_scalar(acc: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    acc.iter()
+        .zip(weights)
+        .map(|(&a, &w)| (a as i32).clamp(0, QA) * w as i32)
+        .sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_avx2(acc: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    use std::arch::x86_64::*;
+    let zero = _mm256_setzero_si256();
+    let qa = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..HIDDEN).step_by(16) {
+        // SAFETY: i + 16 <= HIDDEN, unaligned loads
+        let (a, w) = unsafe {
+            (
+                _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i),
+                _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i),
+            )
+        };
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), qa);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, w));
+    }
+    let half = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256::<1>(sum));
+    let quarter = _mm_add_epi32(half, _mm_shuffle_epi32::<0b01_00_11_10>(half));
+    let single = _mm_add_epi32(quarter, _mm_shuffle_epi32::<0b10_11_00_01>(quarter));
+    _mm_cvtsi128_si32(single)
+}
+
+// Score in centipawns from the point of view of `color`, the side to move
+pub fn evaluate(net: &'static Network, board: &Board, color: Color) -> i32 {
+    let refreshed;
+    let acc = match &board.accumulator {
+        Some(acc) if acc.is_for(net) => acc,
+        _ => {
+            refreshed = Accumulator::new(net, board);
+            &refreshed
+        }
+    };
+    let us = &acc.values[color as usize];
+    let them = &acc.values[color.opposite() as usize];
+    let output = dot(us, &net.output_weights[0]) + dot(them, &net.output_weights[1]) + net.output_bias as i32;
+    output * SCALE / (QA * QB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn random_network() -> &'static Network {
+        let mut rng = rand::thread_rng();
+        let mut row = || std::array::from_fn(|_| rng.gen_range(-64..64));
+        let net = Network {
+            feature_weights: (0..INPUTS).map(|_| row()).collect(),
+            feature_bias: row(),
+            output_weights: [row(), row()],
+            output_bias: 0,
+        };
+        Box::leak(Box::new(net))
+    }
+
+    // Plays the moves keeping the accumulator up to date, and checks it
+    // against a full refresh after each of them
+    fn check_updates(fen: &str, moves: &[(Square, Square)]) {
+        let net = random_network();
+        let (mut board, _) = Board::from_fen(fen).unwrap();
+        board.accumulator = Some(Box::new(Accumulator::new(net, &board)));
+        for &(from, to) in moves {
+            assert!(board.make_move_with_network(Some(net), from, to));
+            let updated = board.accumulator.as_ref().unwrap();
+            assert!(updated.is_for(net));
+            assert_eq!(updated.values, Accumulator::new(net, &board).values);
+        }
+    }
+
+    #[test]
+    fn castling_updates_match_a_refresh() {
+        // e1g1, e8c8
+        check_updates("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &[(0x74, 0x76), (0x04, 0x02)]);
+    }
+
+    #[test]
+    fn en_passant_updates_match_a_refresh() {
+        // d7d5, e5xd6
+        check_updates("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1", &[(0x13, 0x33), (0x34, 0x23)]);
+    }
+
+    #[test]
+    fn promotion_updates_match_a_refresh() {
+        // a7xb8=Q, e8e7, h7h8=Q
+        check_updates("1r2k3/P6P/8/8/8/8/8/4K3 w - - 0 1", &[(0x10, 0x01), (0x04, 0x14), (0x17, 0x07)]);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_dot_matches_the_scalar_one() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            // Beyond both ends of the clipping range
+            let acc = std::array::from_fn(|_| rng.gen_range(-300..600));
+            let weights = std::array::from_fn(|_| rng.gen_range(-128..128));
+            // SAFETY: AVX2 support was checked above
+            assert_eq!(unsafe { dot_avx2(&acc, &weights) }, dot_scalar(&acc, &weights));
+        }
+    }
+}
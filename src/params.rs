@@ -0,0 +1,428 @@
+// src/params.rs
+use crate::eval::Score;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use serde_json::Value;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+
+// Every evaluation weight. Arrays by piece type are in the order Pawn,
+// Knight, Bishop, Rook, Queen, King; piece-square tables are seen from
+// White with rank 8 first. Missing fields in a file keep their defaults.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    pub piece_values_mg: [i32; 6],
+    pub piece_values_eg: [i32; 6],
+    pub pst_mg: [[[i32; 8]; 8]; 6],
+    pub pst_eg: [[[i32; 8]; 8]; 6],
+
+    pub mobility_weights: [Score; 6],
+    pub mobility_baseline: [i32; 6],
+
+    pub doubled: Score,
+    pub isolated: Score,
+    pub backward: Score,
+    pub connected: [Score; 8],
+    pub passed: [Score; 8],
+    pub passed_enemy_king_distance: i32,
+    pub passed_own_king_distance: i32,
+    pub unstoppable_passer: Score,
+
+    pub pawn_shield: [i32; 8],
+    pub pawn_storm: [i32; 8],
+    pub semi_open_file_near_king: i32,
+    pub open_file_near_king: i32,
+    pub king_attack_weights: [i32; 6],
+    pub king_danger: Vec<i32>,
+
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_on_seventh: Score,
+    pub connected_rooks: Score,
+    pub knight_outpost: Score,
+    pub bishop_outpost: Score,
+    pub bad_bishop: Score,
+    pub trapped_bishop: Score,
+    pub trapped_rook: Score,
+}
+
+// Default values
+// Pawn, Knight, Bishop, Rook, Queen, King
+const PIECE_VALUES_MG: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+const PIECE_VALUES_EG: [i32; 6] = [120, 300, 320, 530, 950, 20000];
+
+// Pawn structure
+const DOUBLED: Score = Score::new(-10, -25);
+const ISOLATED: Score = Score::new(-10, -15);
+const BACKWARD: Score = Score::new(-8, -12);
+
+// Indexed by relative rank, 0 is the side's own back rank
+const CONNECTED: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(5, 2),
+    Score::new(7, 4),
+    Score::new(10, 6),
+    Score::new(18, 12),
+    Score::new(30, 25),
+    Score::new(50, 40),
+    Score::new(0, 0),
+];
+const PASSED: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(5, 10),
+    Score::new(10, 15),
+    Score::new(15, 25),
+    Score::new(30, 45),
+    Score::new(55, 80),
+    Score::new(90, 130),
+    Score::new(0, 0),
+];
+
+// Endgame bonus per square of distance between the enemy king (resp. our
+// king) and the square in front of a passed pawn, scaled by how far it is
+const PASSED_ENEMY_KING_DISTANCE: i32 = 5;
+const PASSED_OWN_KING_DISTANCE: i32 = 2;
+// A passer the enemy king cannot catch in a pawn endgame will promote
+const UNSTOPPABLE_PASSER: Score = Score::new(0, 600);
+
+// Mobility: bonus per safe square above (or penalty below) a typical count
+// for the piece type. Pawns and kings have no mobility term.
+const MOBILITY_WEIGHTS: [Score; 6] = [
+    Score::new(0, 0),
+    Score::new(4, 4),
+    Score::new(5, 5),
+    Score::new(2, 4),
+    Score::new(1, 2),
+    Score::new(0, 0),
+];
+const MOBILITY_BASELINE: [i32; 6] = [0, 4, 7, 7, 14, 0];
+
+// King safety. Shield and storm are indexed by the relative rank of the pawn
+// as seen from the king's side; 0 means no pawn on the file.
+const PAWN_SHIELD: [i32; 8] = [-15, 0, 10, 5, 0, 0, 0, 0];
+const PAWN_STORM: [i32; 8] = [0, 0, -20, -12, -6, 0, 0, 0];
+const SEMI_OPEN_FILE_NEAR_KING: i32 = -10;
+const OPEN_FILE_NEAR_KING: i32 = -20;
+// Attack units per king zone square attacked, by attacker type
+const KING_ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+const KING_DANGER: [i32; 100] = [
+    0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+    18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+    68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500
+];
+
+// Piece-specific terms
+const BISHOP_PAIR: Score = Score::new(30, 50);
+const ROOK_OPEN_FILE: Score = Score::new(40, 20);
+const ROOK_SEMI_OPEN_FILE: Score = Score::new(20, 10);
+const ROOK_ON_SEVENTH: Score = Score::new(20, 40);
+const CONNECTED_ROOKS: Score = Score::new(15, 5);
+const KNIGHT_OUTPOST: Score = Score::new(25, 15);
+const BISHOP_OUTPOST: Score = Score::new(12, 6);
+// Per own pawn on the bishop's square colour
+const BAD_BISHOP: Score = Score::new(-3, -5);
+// Bishop on a7/h7 (a2/h2 for Black) cut off by a pawn on b6/g6
+const TRAPPED_BISHOP: Score = Score::new(-100, -100);
+// Rook boxed in the corner by its own uncastled king
+const TRAPPED_ROOK: Score = Score::new(-45, -10);
+
+// Middlegame piece-square tables
+const PAWN_PST_MG: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+    5,  5, 10, 25, 25, 10,  5,  5,
+    0,  0,  0, 20, 20,  0,  0,  0,
+    5, -5,-10,  0,  0,-10, -5,  5,
+    5, 10, 10,-20,-20, 10, 10,  5,
+    0,  0,  0,  0,  0,  0,  0,  0
+];
+
+const KNIGHT_PST_MG: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50
+];
+
+const BISHOP_PST_MG: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20
+];
+
+const ROOK_PST_MG: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    0,  0,  0,  5,  5,  0,  0,  0
+];
+
+const QUEEN_PST_MG: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -5,  0,  5,  5,  5,  5,  0, -5,
+    0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20
+];
+
+const KING_PST_MG: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+    20, 20,  0,  0,  0,  0, 20, 20,
+    20, 30, 10,  0,  0, 10, 30, 20
+];
+
+// Endgame piece-square tables: pawns are pushed, the king is centralized
+const PAWN_PST_EG: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    15, 15, 15, 15, 15, 15, 15, 15,
+    5,  5,  5,  5,  5,  5,  5,  5,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0
+];
+
+const KNIGHT_PST_EG: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,-10, -5, -5,-10,-20,-40,
+    -30,-10, 10, 15, 15, 10,-10,-30,
+    -30, -5, 15, 20, 20, 15, -5,-30,
+    -30, -5, 15, 20, 20, 15, -5,-30,
+    -30,-10, 10, 15, 15, 10,-10,-30,
+    -40,-20,-10, -5, -5,-10,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50
+];
+
+const BISHOP_PST_EG: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20
+];
+
+const ROOK_PST_EG: [i32; 64] = [
+    5,  5,  5,  5,  5,  5,  5,  5,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0
+];
+
+const QUEEN_PST_EG: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -10,  5, 10, 10, 10, 10,  5,-10,
+    -5,  5, 10, 15, 15, 10,  5, -5,
+    -5,  5, 10, 15, 15, 10,  5, -5,
+    -10,  5, 10, 10, 10, 10,  5,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20
+];
+
+const KING_PST_EG: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50
+];
+
+fn rows(table: [i32; 64]) -> [[i32; 8]; 8] {
+    let mut rows = [[0; 8]; 8];
+    for (i, value) in table.into_iter().enumerate() {
+        rows[i / 8][i % 8] = value;
+    }
+    rows
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            piece_values_mg: PIECE_VALUES_MG,
+            piece_values_eg: PIECE_VALUES_EG,
+            pst_mg: [PAWN_PST_MG, KNIGHT_PST_MG, BISHOP_PST_MG, ROOK_PST_MG, QUEEN_PST_MG, KING_PST_MG].map(rows),
+            pst_eg: [PAWN_PST_EG, KNIGHT_PST_EG, BISHOP_PST_EG, ROOK_PST_EG, QUEEN_PST_EG, KING_PST_EG].map(rows),
+            mobility_weights: MOBILITY_WEIGHTS,
+            mobility_baseline: MOBILITY_BASELINE,
+            doubled: DOUBLED,
+            isolated: ISOLATED,
+            backward: BACKWARD,
+            connected: CONNECTED,
+            passed: PASSED,
+            passed_enemy_king_distance: PASSED_ENEMY_KING_DISTANCE,
+            passed_own_king_distance: PASSED_OWN_KING_DISTANCE,
+            unstoppable_passer: UNSTOPPABLE_PASSER,
+            pawn_shield: PAWN_SHIELD,
+            pawn_storm: PAWN_STORM,
+            semi_open_file_near_king: SEMI_OPEN_FILE_NEAR_KING,
+            open_file_near_king: OPEN_FILE_NEAR_KING,
+            king_attack_weights: KING_ATTACK_WEIGHTS,
+            king_danger: KING_DANGER.to_vec(),
+            bishop_pair: BISHOP_PAIR,
+            rook_open_file: ROOK_OPEN_FILE,
+            rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+            rook_on_seventh: ROOK_ON_SEVENTH,
+            connected_rooks: CONNECTED_ROOKS,
+            knight_outpost: KNIGHT_OUTPOST,
+            bishop_outpost: BISHOP_OUTPOST,
+            bad_bishop: BAD_BISHOP,
+            trapped_bishop: TRAPPED_BISHOP,
+            trapped_rook: TRAPPED_ROOK,
+        }
+    }
+}
+
+impl EvalParams {
+    // JSON file; fields that are left out keep their default value
+    pub fn load(path: &str) -> io::Result<EvalParams> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_json(&mut writer, &serde_json::to_value(self)?, 0)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+// Like serde_json's pretty printer, except that arrays of plain values and
+// small objects (scores) stay on one line, so a piece-square table row or a
+// list of per-piece weights reads like the source
+fn write_json(w: &mut impl Write, value: &Value, indent: usize) -> io::Result<()> {
+    let inline = |v: &Value| !v.is_array() && !v.is_object();
+    let pad = "  ".repeat(indent + 1);
+    match value {
+        Value::Array(items) if !items.iter().all(inline) => {
+            writeln!(w, "[")?;
+            for (i, item) in items.iter().enumerate() {
+                write!(w, "{}", pad)?;
+                write_json(w, item, indent + 1)?;
+                writeln!(w, "{}", if i + 1 < items.len() { "," } else { "" })?;
+            }
+            write!(w, "{}]", "  ".repeat(indent))
+        }
+        Value::Object(fields) if !fields.values().all(inline) => {
+            writeln!(w, "{{")?;
+            for (i, (name, item)) in fields.iter().enumerate() {
+                write!(w, "{}{}: ", pad, Value::from(name.as_str()))?;
+                write_json(w, item, indent + 1)?;
+                writeln!(w, "{}", if i + 1 < fields.len() { "," } else { "" })?;
+            }
+            write!(w, "{}}}", "  ".repeat(indent))
+        }
+        _ => write!(w, "{}", value),
+    }
+}
+
+lazy_static! {
+    // The parameters used by `evaluate`, shared by all search threads
+    static ref PARAMS: RwLock<EvalParams> = RwLock::new(EvalParams::default());
+}
+
+// Bumped on every `set` so caches of evaluation terms can tell they are stale
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn get() -> RwLockReadGuard<'static, EvalParams> {
+    PARAMS.read().unwrap()
+}
+
+pub fn set(params: EvalParams) {
+    *PARAMS.write().unwrap() = params;
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("params-test-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn saved_parameters_load_back_unchanged() {
+        let mut params = EvalParams::default();
+        params.pst_mg[0][3][4] = 17;
+        params.isolated = Score::new(-3, -4);
+        params.king_danger.push(999);
+
+        let mut json = Vec::new();
+        write_json(&mut json, &serde_json::to_value(&params).unwrap(), 0).unwrap();
+        let parsed: EvalParams = serde_json::from_slice(&json).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(&params).unwrap());
+
+        let path = temp_file("saved.json");
+        params.save(&path).unwrap();
+        let loaded = EvalParams::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&params).unwrap());
+    }
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let path = temp_file("partial.json");
+        std::fs::write(&path, r#"{ "piece_values_mg": [90, 300, 310, 480, 880, 20000] }"#).unwrap();
+        let loaded = EvalParams::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.piece_values_mg, [90, 300, 310, 480, 880, 20000]);
+        assert_eq!(loaded.piece_values_eg, PIECE_VALUES_EG);
+        assert_eq!(loaded.passed_own_king_distance, PASSED_OWN_KING_DISTANCE);
+        assert_eq!(loaded.king_danger, KING_DANGER);
+        assert_eq!(
+            serde_json::to_value(loaded.isolated).unwrap(),
+            serde_json::to_value(ISOLATED).unwrap()
+        );
+    }
+}
@@ -0,0 +1,244 @@
+// src/pawns.rs
+use crate::eval::Score;
+use crate::params::{self, EvalParams};
+use crate::{zobrist, Board, Color, Piece, PieceType, Square};
+use std::cell::RefCell;
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// Everything about the pawn structure that does not depend on other pieces
+#[derive(Copy, Clone, Default)]
+struct PawnEntry {
+    key: u64,
+    // Parameter generation the scores were computed with
+    generation: u64,
+    score: [Score; 2],
+    passed: [Vec64; 2],
+}
+
+// Set of squares, bit = row * 8 + file of the 0x88 square
+type Vec64 = u64;
+
+thread_local! {
+    // One table per search thread, so there is nothing to synchronise
+    static PAWN_TABLE: RefCell<Vec<PawnEntry>> = RefCell::new(vec![PawnEntry::default(); PAWN_TABLE_SIZE]);
+}
+
+fn bit(sq: Square) -> Vec64 {
+    1 << ((sq >> 4) * 8 + (sq & 7))
+}
+
+fn square_from_bit(idx: u32) -> Square {
+    ((idx / 8) * 16 + idx % 8) as Square
+}
+
+// 0 for the side's back rank up to 7 for the promotion rank
+pub fn relative_rank(sq: Square, color: Color) -> i32 {
+    let row = (sq >> 4) as i32;
+    if color == Color::White { 7 - row } else { row }
+}
+
+pub fn forward(color: Color) -> i8 {
+    if color == Color::White { -16 } else { 16 }
+}
+
+pub fn distance(a: Square, b: Square) -> i32 {
+    let file_diff = ((a & 7) as i32 - (b & 7) as i32).abs();
+    let rank_diff = ((a >> 4) as i32 - (b >> 4) as i32).abs();
+    file_diff.max(rank_diff)
+}
+
+fn is_pawn(board: &Board, sq: Square, color: Color) -> bool {
+    matches!(board.get_piece(sq), Some(Piece { kind: PieceType::Pawn, color: c }) if c == color)
+}
+
+fn on_file(pawns: &[Square], file: i32) -> impl Iterator<Item = &Square> {
+    pawns.iter().filter(move |&&p| (p & 7) as i32 == file)
+}
+
+fn analyze(params: &EvalParams, board: &Board, key: u64, generation: u64) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key,
+        generation,
+        ..PawnEntry::default()
+    };
+
+    let mut pawns: [Vec<Square>; 2] = [Vec::new(), Vec::new()];
+    for sq in 0u8..128 {
+        if let Some(Piece {
+            kind: PieceType::Pawn,
+            color,
+        }) = board.get_piece(sq)
+        {
+            pawns[color as usize].push(sq);
+        }
+    }
+
+    for color in [Color::White, Color::Black] {
+        let us = color as usize;
+        let them = color.opposite();
+        let fwd = forward(color);
+        let mut score = Score::default();
+
+        for &sq in &pawns[us] {
+            let file = (sq & 7) as i32;
+            let rank = relative_rank(sq, color);
+            let neighbours: Vec<Square> = on_file(&pawns[us], file - 1)
+                .chain(on_file(&pawns[us], file + 1))
+                .copied()
+                .collect();
+            let isolated = neighbours.is_empty();
+            let doubled = on_file(&pawns[us], file).any(|&p| relative_rank(p, color) > rank);
+            let supported = [fwd - 1, fwd + 1]
+                .iter()
+                .any(|&d| is_pawn(board, sq.wrapping_sub(d as u8), color));
+            let phalanx = [-1i8, 1].iter().any(|&d| is_pawn(board, sq.wrapping_add(d as u8), color));
+
+            let stoppers = pawns[them as usize].iter().any(|&p| {
+                ((p & 7) as i32 - file).abs() <= 1 && relative_rank(p, color) > rank
+            });
+
+            // Backward: no friendly pawn can come to its support and the
+            // square in front of it is controlled by an enemy pawn
+            let stop = sq.wrapping_add(fwd as u8);
+            let stop_attacked = [fwd - 1, fwd + 1]
+                .iter()
+                .any(|&d| is_pawn(board, stop.wrapping_add(d as u8), them));
+            let backward = !isolated
+                && !supported
+                && !phalanx
+                && neighbours.iter().all(|&p| relative_rank(p, color) > rank)
+                && stop_attacked;
+
+            if doubled {
+                score += params.doubled;
+            }
+            if isolated {
+                score += params.isolated;
+            } else if backward {
+                score += params.backward;
+            }
+            if supported || phalanx {
+                score += params.connected[rank as usize];
+            }
+            if !stoppers && !doubled {
+                score += params.passed[rank as usize];
+                entry.passed[us] |= bit(sq);
+            }
+        }
+        entry.score[us] = score;
+    }
+
+    entry
+}
+
+fn probe(params: &EvalParams, board: &Board) -> PawnEntry {
+    let key = zobrist::pawn_hash(board);
+    let generation = params::generation();
+    PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let slot = &mut table[key as usize & (PAWN_TABLE_SIZE - 1)];
+        if slot.key != key || slot.generation != generation {
+            *slot = analyze(params, board, key, generation);
+        }
+        *slot
+    })
+}
+
+fn has_pieces(board: &Board, color: Color) -> bool {
+    (0u8..128).any(|sq| {
+        matches!(board.get_piece(sq), Some(p) if p.color == color && p.kind != PieceType::Pawn && p.kind != PieceType::King)
+    })
+}
+
+// Pawn structure of `color` plus the passed pawn terms that depend on the
+// kings. `side_to_move` matters for the race in pawn endgames.
+pub fn evaluate_pawns(params: &EvalParams, board: &Board, color: Color, side_to_move: Color) -> Score {
+    let entry = probe(params, board);
+    let us = color as usize;
+    let mut score = entry.score[us];
+
+    let (Some(own_king), Some(enemy_king)) = (board.king_square(color), board.king_square(color.opposite())) else {
+        return score;
+    };
+    let pawn_endgame = !has_pieces(board, color.opposite());
+    let fwd = forward(color);
+
+    let mut passed = entry.passed[us];
+    while passed != 0 {
+        let sq = square_from_bit(passed.trailing_zeros());
+        passed &= passed - 1;
+        let rank = relative_rank(sq, color);
+
+        if (3..7).contains(&rank) {
+            let block = sq.wrapping_add(fwd as u8);
+            let weight = rank - 2;
+            score.eg += (distance(enemy_king, block) * params.passed_enemy_king_distance
+                - distance(own_king, block) * params.passed_own_king_distance)
+                * weight;
+        }
+
+        // Rule of the square, with a clear path to the promotion square
+        if pawn_endgame {
+            let promotion = if color == Color::White { sq & 7 } else { 0x70 | (sq & 7) };
+            let mut path_clear = true;
+            let mut to = sq;
+            while to != promotion {
+                to = to.wrapping_add(fwd as u8);
+                if board.get_piece(to).is_some() {
+                    path_clear = false;
+                    break;
+                }
+            }
+            let pawn_moves = 7 - rank - if rank == 1 { 1 } else { 0 };
+            let king_moves = distance(enemy_king, promotion) - if side_to_move == color { 0 } else { 1 };
+            if path_clear && pawn_moves < king_moves {
+                score += params.unstoppable_passer;
+            }
+        }
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Structure scores of White and Black, without the king terms
+    fn structure(fen: &str) -> [Score; 2] {
+        let (board, _) = Board::from_fen(fen).unwrap();
+        analyze(&EvalParams::default(), &board, 0, 0).score
+    }
+
+    #[test]
+    fn doubled_and_isolated_pawns() {
+        let params = EvalParams::default();
+        // c2 is doubled, and both white pawns are isolated; c7 blocks them
+        let [white, black] = structure("4k3/2p5/8/8/8/2P5/2P5/4K3 w - - 0 1");
+        assert_eq!(white, params.doubled + params.isolated * 2);
+        assert_eq!(black, params.isolated);
+    }
+
+    #[test]
+    fn backward_pawn() {
+        let params = EvalParams::default();
+        // d3 cannot catch up with c4 and e5 controls d4; c4, supported by
+        // d3, is passed
+        let [white, _] = structure("4k3/8/8/4p3/2P5/3P4/8/4K3 w - - 0 1");
+        assert_eq!(white, params.backward + params.connected[3] + params.passed[3]);
+    }
+
+    #[test]
+    fn passed_pawns() {
+        let params = EvalParams::default();
+        let (board, _) = Board::from_fen("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+        let entry = analyze(&params, &board, 0, 0);
+        assert_eq!(entry.score[Color::White as usize], params.isolated + params.passed[4]);
+        assert_eq!(entry.passed[Color::White as usize], bit(0x33));
+
+        // A pawn on a neighbouring file ahead of it can stop it
+        let [white, _] = structure("4k3/4p3/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(white, params.isolated);
+    }
+}
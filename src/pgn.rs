@@ -0,0 +1,742 @@
+// src/pgn.rs
+//
+// Games in PGN (Portable Game Notation). A `Game` is a tree of moves from
+// its start position: every node is a move and its children are the moves
+// that followed it, the main line first and then the variations. A cursor
+// marks the current position; moves played there follow the tree if it
+// already has them and start a new variation if not.
+//
+// Games are written in export format: the seven standard tags, FEN and
+// SetUp when the game did not start from the initial position, then the
+// moves in algebraic notation wrapped at 80 columns. `parse` reads them
+// back, with comments, NAGs and nested variations. Moves must be legal for
+// the engine's board, which promotes only to a queen, so a game with an
+// underpromotion cannot be read; an error costs only the game it is in.
+use crate::{san, Board, Color, Square};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LINE_WIDTH: usize = 80;
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+// Move suffixes and the NAGs they stand for
+const SUFFIXES: [(&str, u8); 6] = [("!!", 3), ("??", 4), ("!?", 5), ("?!", 6), ("!", 1), ("?", 2)];
+
+pub struct Node {
+    // None only for the root, the start position
+    pub mv: Option<(Square, Square)>,
+    pub san: String,
+    pub nags: Vec<u8>,
+    // Comments before and after the move
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    pub parent: Option<usize>,
+    // The main line first, then the variations
+    pub children: Vec<usize>,
+}
+
+pub struct Game {
+    // In output order; the seven standard tags come first
+    pub tags: Vec<(String, String)>,
+    start: Board,
+    start_color: Color,
+    nodes: Vec<Node>,
+    current: usize,
+    // Position at the current node
+    board: Board,
+    color: Color,
+}
+
+// Today's date as PGN writes it, e.g. 2024.05.17 (UTC)
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86400) as i64;
+    // Days since 1970-01-01 to a civil date, counting in 400-year eras from March 1
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn add_comment(comment: &mut Option<String>, text: &str) {
+    match comment {
+        Some(c) => {
+            c.push(' ');
+            c.push_str(text);
+        }
+        None => *comment = Some(text.to_string()),
+    }
+}
+
+impl Game {
+    pub fn new(board: &Board, color: Color) -> Game {
+        let root = Node {
+            mv: None,
+            san: String::new(),
+            nags: Vec::new(),
+            comment_before: None,
+            comment: None,
+            parent: None,
+            children: Vec::new(),
+        };
+        let mut game = Game {
+            tags: Vec::new(),
+            start: board.clone(),
+            start_color: color,
+            nodes: vec![root],
+            current: 0,
+            board: board.clone(),
+            color,
+        };
+        for (name, value) in [("Event", "?"), ("Site", "?"), ("Date", &today()), ("Round", "-")] {
+            game.set_tag(name, value);
+        }
+        for name in ["White", "Black"] {
+            game.set_tag(name, "?");
+        }
+        game.set_tag("Result", "*");
+        let mut initial = Board::new();
+        initial.setup_starting_position();
+        if color != Color::White || initial.to_fen(Color::White) != board.to_fen(color) {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &board.to_fen(color));
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    // Replaces the tag's value, or adds the tag at the end
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn set_result(&mut self, result: &str) {
+        self.set_tag("Result", result);
+    }
+
+    // Whether no move has been played or read
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    pub fn node(&self, id: usize) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn start_color(&self) -> Color {
+        self.start_color
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // The position at the cursor and the side to move there
+    pub fn position(&self) -> (&Board, Color) {
+        (&self.board, self.color)
+    }
+
+    // The move that led to the current position
+    pub fn last_move(&self) -> Option<(Square, Square)> {
+        self.nodes[self.current].mv
+    }
+
+    // Number of moves from the start to the cursor
+    pub fn ply(&self) -> usize {
+        let mut ply = 0;
+        let mut node = self.current;
+        while let Some(parent) = self.nodes[node].parent {
+            ply += 1;
+            node = parent;
+        }
+        ply
+    }
+
+    // Plays a legal move at the cursor, following the tree if it has the
+    // move already, and moves the cursor to it
+    pub fn play(&mut self, mv: (Square, Square), comment: Option<String>) -> usize {
+        let existing = self.nodes[self.current].children.iter().copied().find(|&c| self.nodes[c].mv == Some(mv));
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = self.nodes.len();
+                self.nodes.push(Node {
+                    mv: Some(mv),
+                    san: san::to_san(&self.board, self.color, mv),
+                    nags: Vec::new(),
+                    comment_before: None,
+                    comment: None,
+                    parent: Some(self.current),
+                    children: Vec::new(),
+                });
+                self.nodes[self.current].children.push(id);
+                id
+            }
+        };
+        if comment.is_some() {
+            self.nodes[id].comment = comment;
+        }
+        self.board.make_move(mv.0, mv.1);
+        self.color = self.color.opposite();
+        self.current = id;
+        id
+    }
+
+    // Moves the cursor to a node, replaying the moves from the start
+    pub fn go_to(&mut self, id: usize) {
+        let mut path = Vec::new();
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            path.push(node);
+            node = parent;
+        }
+        self.board = self.start.clone();
+        self.color = self.start_color;
+        for &node in path.iter().rev() {
+            let (from, to) = self.nodes[node].mv.unwrap();
+            self.board.make_move(from, to);
+            self.color = self.color.opposite();
+        }
+        self.current = id;
+    }
+
+    // One move back; false at the start
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => {
+                self.go_to(parent);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // One move forward, along the main line (0) or a variation (1, 2, ...);
+    // false if there is no such move
+    pub fn forward(&mut self, variation: usize) -> bool {
+        match self.nodes[self.current].children.get(variation) {
+            Some(&child) => {
+                let (from, to) = self.nodes[child].mv.unwrap();
+                self.board.make_move(from, to);
+                self.color = self.color.opposite();
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Follows the main line from the cursor to its last move
+    pub fn go_to_end(&mut self) {
+        while self.forward(0) {}
+    }
+
+    // The game in PGN, with the comments if `comments` is set
+    pub fn to_pgn(&self, comments: bool) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if comments && let Some(comment) = &self.nodes[0].comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        self.write_moves(0, self.start_color, 1, false, comments, &mut tokens);
+        tokens.push(self.tag("Result").unwrap_or("*").to_string());
+
+        let mut line = String::new();
+        let mut last = String::new();
+        for token in tokens {
+            let space = !line.is_empty() && last != "(" && token != ")";
+            if space && line.len() + 1 + token.len() > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            } else if space {
+                line.push(' ');
+            }
+            line.push_str(&token);
+            last = token;
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+        pgn
+    }
+
+    // One move: its number when White plays it or when `numbered` is not
+    // set, its comments and NAGs. Returns whether the next move can go
+    // without a number.
+    fn write_move(&self, id: usize, color: Color, number: u32, numbered: bool, comments: bool, tokens: &mut Vec<String>) -> bool {
+        let node = &self.nodes[id];
+        if comments && let Some(comment) = &node.comment_before {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        if color == Color::White {
+            tokens.push(format!("{}.", number));
+        } else if !numbered || comments && node.comment_before.is_some() {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(node.san.clone());
+        tokens.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+        match &node.comment {
+            Some(comment) if comments => {
+                tokens.push(format!("{{{}}}", comment));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    // The moves after `id`: the main line, and the variations to each of
+    // its moves in parentheses right after it
+    fn write_moves(&self, id: usize, color: Color, number: u32, numbered: bool, comments: bool, tokens: &mut Vec<String>) {
+        let (mut id, mut color, mut number, mut numbered) = (id, color, number, numbered);
+        while let Some((&main, variations)) = self.nodes[id].children.split_first() {
+            numbered = self.write_move(main, color, number, numbered, comments, tokens);
+            let next_number = if color == Color::Black { number + 1 } else { number };
+            for &variation in variations {
+                tokens.push("(".to_string());
+                let next_numbered = self.write_move(variation, color, number, false, comments, tokens);
+                self.write_moves(variation, color.opposite(), next_number, next_numbered, comments, tokens);
+                tokens.push(")".to_string());
+                numbered = false;
+            }
+            id = main;
+            color = color.opposite();
+            number = next_number;
+        }
+    }
+
+    // Writes the game to `path`, after the games already in it if `append`
+    pub fn save(&self, path: &str, append: bool, comments: bool) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(path)?;
+        file.write_all(self.to_pgn(comments).as_bytes())
+    }
+}
+
+// Where reading PGN failed, counting lines and columns from 1
+#[derive(Debug)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    // Past the current game's tags
+    in_moves: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn here(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    fn error<T>(&self, (line, column): (usize, usize), message: impl Into<String>) -> Result<T, PgnError> {
+        Err(PgnError { line, column, message: message.into() })
+    }
+
+    // Skips white space, and lines starting with the '%' escape
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '%' && self.column == 1 {
+                while self.bump().is_some_and(|c| c != '\n') {}
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Reads up to `end`, which is consumed but not returned
+    fn until(&mut self, end: char) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            match self.bump()? {
+                c if c == end => return Some(text),
+                c => text.push(c),
+            }
+        }
+    }
+
+    // [Name "value"]
+    fn tag(&mut self) -> Result<(String, String), PgnError> {
+        let start = self.here();
+        self.bump();
+        self.skip_space();
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+            self.bump();
+        }
+        if name.is_empty() {
+            return self.error(self.here(), "expected a tag name");
+        }
+        self.skip_space();
+        if self.peek() != Some('"') {
+            return self.error(self.here(), "expected the tag value in quotes");
+        }
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some(c) => value.push(c),
+                    None => return self.error(start, "unterminated tag"),
+                },
+                Some('\n') | None => return self.error(start, "unterminated tag value"),
+                Some(c) => value.push(c),
+            }
+        }
+        self.skip_space();
+        if self.peek() != Some(']') {
+            return self.error(self.here(), "expected ']'");
+        }
+        self.bump();
+        Ok((name, value))
+    }
+
+    // A move, move number or result
+    fn symbol(&mut self) -> String {
+        let mut symbol = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || "+#=:-/.!?_*".contains(*c)) {
+            symbol.push(c);
+            self.bump();
+            if c == '*' {
+                break;
+            }
+        }
+        symbol
+    }
+
+    // Skips the rest of a game that could not be read: its remaining tags
+    // and moves, up to the next line that starts with a tag after them
+    fn skip_game(&mut self) {
+        while self.bump().is_some_and(|c| c != '\n') {}
+        while let Some(c) = self.peek() {
+            if c == '[' && self.in_moves {
+                break;
+            }
+            let mut blank = true;
+            while let Some(c) = self.bump().filter(|&c| c != '\n') {
+                blank &= c.is_whitespace();
+            }
+            self.in_moves |= c != '[' && !blank;
+        }
+    }
+
+    fn game(&mut self) -> Result<Game, PgnError> {
+        self.in_moves = false;
+        let mut tags = Vec::new();
+        let mut fen = None;
+        while self.peek() == Some('[') {
+            let at = self.here();
+            let (name, value) = self.tag()?;
+            if name == "FEN" {
+                fen = Some((value.clone(), at));
+            }
+            tags.push((name, value));
+            self.skip_space();
+        }
+        self.in_moves = true;
+        let (board, color) = match fen {
+            Some((fen, at)) => match Board::from_fen(&fen) {
+                Some(start) => start,
+                None => return self.error(at, "invalid FEN"),
+            },
+            None => {
+                let mut board = Board::new();
+                board.setup_starting_position();
+                (board, Color::White)
+            }
+        };
+        let mut game = Game::new(&board, color);
+        game.tags = tags;
+
+        // Nodes to return to at the end of each open variation, and where it opened
+        let mut variations: Vec<(usize, (usize, usize))> = Vec::new();
+        // Comments at the start of a variation go before its first move
+        let mut variation_start = false;
+        let mut comment_before: Option<String> = None;
+        let mut result = None;
+        loop {
+            self.skip_space();
+            let at = self.here();
+            let Some(c) = self.peek() else { break };
+            match c {
+                '{' | ';' => {
+                    self.bump();
+                    let Some(text) = self.until(if c == '{' { '}' } else { '\n' }).or_else(|| {
+                        // A ';' comment may end the file
+                        (c == ';').then(String::new)
+                    }) else {
+                        return self.error(at, "unterminated comment");
+                    };
+                    if variation_start {
+                        add_comment(&mut comment_before, text.trim());
+                    } else {
+                        add_comment(&mut game.nodes[game.current].comment, text.trim());
+                    }
+                }
+                '(' => {
+                    self.bump();
+                    let Some(parent) = game.nodes[game.current].parent else {
+                        return self.error(at, "variation without a move before it");
+                    };
+                    variations.push((game.current, at));
+                    game.go_to(parent);
+                    variation_start = true;
+                }
+                ')' => {
+                    self.bump();
+                    let Some((back, _)) = variations.pop() else {
+                        return self.error(at, "')' without a variation to close");
+                    };
+                    game.go_to(back);
+                    variation_start = false;
+                }
+                '$' => {
+                    self.bump();
+                    let digits = self.symbol();
+                    let Ok(nag) = digits.parse::<u8>() else {
+                        return self.error(at, "expected a NAG number after '$'");
+                    };
+                    if game.current == 0 {
+                        return self.error(at, "NAG before any move");
+                    }
+                    game.nodes[game.current].nags.push(nag);
+                }
+                '[' => break,
+                _ => {
+                    let symbol = self.symbol();
+                    if symbol.is_empty() {
+                        return self.error(at, format!("unexpected character '{}'", c));
+                    }
+                    if RESULTS.contains(&symbol.as_str()) {
+                        result = Some((symbol, at));
+                        break;
+                    }
+                    // Move numbers, also glued to the move as in "12.e4"
+                    let (number, text) = match symbol.rfind('.') {
+                        Some(i) => symbol.split_at(i + 1),
+                        None if symbol.bytes().all(|b| b.is_ascii_digit()) => (symbol.as_str(), ""),
+                        None => ("", symbol.as_str()),
+                    };
+                    if !number.trim_end_matches('.').bytes().all(|b| b.is_ascii_digit()) {
+                        return self.error(at, format!("invalid move number '{}'", number));
+                    }
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let at = (at.0, at.1 + number.chars().count());
+                    let (text, nag) = match SUFFIXES.iter().find(|(s, _)| text.ends_with(s)) {
+                        Some(&(suffix, nag)) => (&text[..text.len() - suffix.len()], Some(nag)),
+                        None => (text, None),
+                    };
+                    let (board, color) = game.position();
+                    let mv = match san::parse(board, color, text) {
+                        Ok(mv) => mv,
+                        Err(e) => return self.error(at, format!("{}: {}", text, e)),
+                    };
+                    let id = game.play(mv, None);
+                    if let Some(comment) = comment_before.take() {
+                        add_comment(&mut game.nodes[id].comment_before, &comment);
+                    }
+                    game.nodes[id].nags.extend(nag);
+                    variation_start = false;
+                }
+            }
+        }
+
+        if let Some(&(_, at)) = variations.last() {
+            return self.error(at, "unterminated variation");
+        }
+        if let Some((result, at)) = result {
+            if game.tag("Result").is_some_and(|r| r != result && r != "*") {
+                return self.error(at, format!("result {} does not match the Result tag", result));
+            }
+            game.set_result(&result);
+        }
+        game.go_to(0);
+        Ok(game)
+    }
+}
+
+// Reads every game in the text. A game with an error is skipped up to the
+// next game's tags and its place in the list holds the error.
+pub fn parse(text: &str) -> Vec<Result<Game, PgnError>> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0, line: 1, column: 1, in_moves: false };
+    let mut games = Vec::new();
+    loop {
+        parser.skip_space();
+        if parser.peek().is_none() {
+            return games;
+        }
+        let game = parser.game();
+        if game.is_err() {
+            parser.skip_game();
+        }
+        games.push(game);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = r#"[Event "Test"]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "1"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+{Opening} 1. e4 e5 $1 2. Nf3 {develops} (2. f4 exf4 (2... d5) 3. Nf3 $2) 2... Nc6
+3. Bc4 Nf6 4. O-O Bc5 5. d4 exd4 1-0
+
+"#;
+
+    // The movetext on one line
+    fn movetext(pgn: &str) -> String {
+        let moves = pgn.lines().filter(|line| !line.starts_with('['));
+        moves.flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+    }
+
+    fn parse_one(text: &str) -> Result<Game, PgnError> {
+        let mut games = parse(text);
+        assert_eq!(games.len(), 1);
+        games.remove(0)
+    }
+
+    #[test]
+    fn reads_moves_variations_comments_and_nags() {
+        let mut game = parse_one(GAME).unwrap();
+        assert_eq!(game.tag("White"), Some("A"));
+        assert_eq!(game.tag("Result"), Some("1-0"));
+        assert_eq!(game.node(0).comment.as_deref(), Some("Opening"));
+
+        game.go_to_end();
+        assert_eq!(game.ply(), 10);
+        let mut sans = Vec::new();
+        let mut id = game.current();
+        while let Some(parent) = game.node(id).parent {
+            sans.push(game.node(id).san.clone());
+            id = parent;
+        }
+        sans.reverse();
+        assert_eq!(sans, ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O", "Bc5", "d4", "exd4"]);
+
+        game.go_to(0);
+        assert!(game.forward(0) && game.forward(0));
+        assert_eq!(game.node(game.current()).nags, [1]);
+        let after_e5 = game.node(game.current());
+        assert_eq!(after_e5.children.len(), 2);
+        let (nf3, f4) = (game.node(after_e5.children[0]), game.node(after_e5.children[1]));
+        assert_eq!(nf3.comment.as_deref(), Some("develops"));
+        assert_eq!(f4.san, "f4");
+        let exf4 = game.node(f4.children[0]);
+        assert_eq!(game.node(exf4.children[0]).nags, [2]);
+        assert_eq!(game.node(f4.children[1]).san, "d5");
+    }
+
+    #[test]
+    fn writing_and_reading_again_gives_the_same_game() {
+        let game = parse_one(GAME).unwrap();
+        let written = game.to_pgn(true);
+        assert_eq!(
+            movetext(&written),
+            "{Opening} 1. e4 e5 $1 2. Nf3 {develops} (2. f4 exf4 (2... d5) 3. Nf3 $2) 2... Nc6 3. Bc4 Nf6 4. O-O Bc5 \
+             5. d4 exd4 1-0"
+        );
+        let again = parse_one(&written).unwrap();
+        assert_eq!(again.to_pgn(true), written);
+        assert_eq!(again.tags, game.tags);
+    }
+
+    #[test]
+    fn comments_can_be_left_out() {
+        let game = parse_one(GAME).unwrap();
+        let written = game.to_pgn(false);
+        assert!(!written.contains('{'));
+        assert!(movetext(&written).starts_with("1. e4 e5 $1 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3 $2) 2... Nc6 3. Bc4"));
+    }
+
+    #[test]
+    fn starts_from_a_fen_with_black_to_move() {
+        let text = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n1... Kd7 2. e4 *\n";
+        let mut game = parse_one(text).unwrap();
+        assert!(game.start_color() == Color::Black);
+        game.go_to_end();
+        assert_eq!(game.ply(), 2);
+        assert!(game.to_pgn(true).contains("1... Kd7 2. e4 *"));
+    }
+
+    #[test]
+    fn errors_give_line_and_column() {
+        let text = "[Event \"Test\"]\n\n1. e4 e5\n2. Nf3 Ke7 3. Ke3 *\n";
+        let Err(error) = parse_one(text) else { panic!("Ke3 is illegal") };
+        assert_eq!((error.line, error.column), (4, 15));
+        assert!(error.to_string().starts_with("line 4, column 15: "));
+    }
+
+    #[test]
+    fn an_error_only_costs_its_own_game() {
+        let text = "[Event \"Bad\"]\n\n1. e4 e5 2. Qh6 *\n\n[Event \"Good\"]\n\n1. d4 d5 *\n";
+        let games = parse(text);
+        assert_eq!(games.len(), 2);
+        assert!(matches!(&games[0], Err(e) if e.line == 3));
+        assert!(matches!(&games[1], Ok(game) if game.tag("Event") == Some("Good")));
+    }
+
+    #[test]
+    fn the_result_must_match_its_tag() {
+        assert!(parse_one("[Result \"1-0\"]\n\n1. e4 0-1\n").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// src/san.rs
+//
+// Standard algebraic notation (Nf3, exd5, Nbd2, e8=Q+, O-O). Reading is
+// split in two: `parse_notation` only takes the text apart, `parse` finds
+// the move on the engine's board. The engine promotes only to a queen, so a
+// promotion to another piece is reported as unsupported; a pawn reaching
+// the last rank without a promotion piece is read as promoting to a queen.
+use crate::{has_legal_moves, is_in_check, Board, Color, Piece, PieceType, Square};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SanError {
+    // Not algebraic notation at all
+    Invalid,
+    // An underpromotion
+    Unsupported,
+    // No piece that fits can move there legally
+    Illegal,
+    // More than one piece fits
+    Ambiguous,
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SanError::Invalid => "not a move in algebraic notation",
+            SanError::Unsupported => "promotions other than to a queen are not supported",
+            SanError::Illegal => "illegal move",
+            SanError::Ambiguous => "ambiguous move",
+        })
+    }
+}
+
+// A move as written: the piece, what the text gives of its origin (file
+// 0-7 from a, row 0-7 from rank 8 as on the 0x88 board), the destination and
+// the promotion piece
+#[derive(Clone, Copy, PartialEq)]
+pub enum Notation {
+    Castle { long: bool },
+    Move { kind: PieceType, file: Option<u8>, row: Option<u8>, to: Square, promotion: Option<PieceType> },
+}
+
+impl Notation {
+    // Whether a piece on `from` fits the written origin
+    pub fn fits(&self, from: Square) -> bool {
+        match *self {
+            Notation::Move { file, row, .. } => {
+                file.is_none_or(|f| from & 7 == f) && row.is_none_or(|r| from >> 4 == r)
+            }
+            Notation::Castle { .. } => false,
+        }
+    }
+}
+
+fn piece_kind(c: char) -> Option<PieceType> {
+    match c {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn piece_letter(kind: PieceType) -> &'static str {
+    match kind {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+// Takes a move apart without looking at a board. Check and mate signs and
+// annotations (!, ?) are skipped, and so is a missing or superfluous 'x'.
+pub fn parse_notation(text: &str) -> Result<Notation, SanError> {
+    let text = text.trim_end_matches(['+', '#', '!', '?']);
+    match text {
+        "O-O" | "0-0" => return Ok(Notation::Castle { long: false }),
+        "O-O-O" | "0-0-0" => return Ok(Notation::Castle { long: true }),
+        _ => {}
+    }
+    if !text.is_ascii() {
+        return Err(SanError::Invalid);
+    }
+
+    let (kind, mut rest) = match text.chars().next().and_then(piece_kind) {
+        Some(kind) => (kind, &text[1..]),
+        None => (PieceType::Pawn, text),
+    };
+    let mut promotion = None;
+    if kind == PieceType::Pawn
+        && let Some(last) = rest.chars().last()
+        && let Some(piece) = piece_kind(last).filter(|&p| p != PieceType::King)
+    {
+        promotion = Some(piece);
+        rest = rest[..rest.len() - 1].trim_end_matches('=');
+    }
+
+    let rest = rest.replace('x', "");
+    let bytes = rest.as_bytes();
+    if !(2..=4).contains(&bytes.len()) {
+        return Err(SanError::Invalid);
+    }
+    let to = crate::coords_to_square(&rest[bytes.len() - 2..]).ok_or(SanError::Invalid)?;
+    let (mut file, mut row) = (None, None);
+    for &c in &bytes[..bytes.len() - 2] {
+        match c {
+            b'a'..=b'h' if file.is_none() && row.is_none() => file = Some(c - b'a'),
+            b'1'..=b'8' if row.is_none() => row = Some(b'8' - c),
+            _ => return Err(SanError::Invalid),
+        }
+    }
+    // A pawn names its file only when capturing, and never its rank;
+    // without a file it moves straight ahead
+    if kind == PieceType::Pawn && row.is_some() {
+        return Err(SanError::Invalid);
+    }
+    if kind == PieceType::Pawn && file.is_none() {
+        file = Some(to & 7);
+    }
+    Ok(Notation::Move { kind, file, row, to, promotion })
+}
+
+// The move `text` stands for on the board, with `color` to move
+pub fn parse(board: &Board, color: Color, text: &str) -> Result<(Square, Square), SanError> {
+    let notation = parse_notation(text)?;
+    let (kind, to, promotion) = match notation {
+        Notation::Castle { long } => {
+            let king = board.king_square(color).ok_or(SanError::Illegal)?;
+            let to = if long { king.wrapping_sub(2) } else { king + 2 };
+            let castles = (king & 7) == 4 && board.generate_legal_moves_for_piece(king).contains(&to);
+            return if castles { Ok((king, to)) } else { Err(SanError::Illegal) };
+        }
+        Notation::Move { kind, to, promotion, .. } => (kind, to, promotion),
+    };
+    let last_row = if color == Color::White { 0 } else { 7 };
+    if promotion.is_some() && to >> 4 != last_row {
+        return Err(SanError::Illegal);
+    }
+    if promotion.is_some_and(|p| p != PieceType::Queen) {
+        return Err(SanError::Unsupported);
+    }
+
+    let mut candidates = (0u8..128).filter(|&from| {
+        board.get_piece(from) == Some(Piece { color, kind })
+            && notation.fits(from)
+            && board.generate_legal_moves_for_piece(from).contains(&to)
+    });
+    let from = candidates.next().ok_or(SanError::Illegal)?;
+    if candidates.next().is_some() {
+        return Err(SanError::Ambiguous);
+    }
+    Ok((from, to))
+}
+
+// A move other than castling: the piece, its origin's file, rank or both
+// when another piece of the same kind could also go there, the capture,
+// the destination and =Q for a promotion
+fn move_text(board: &Board, piece: Piece, from: Square, to: Square) -> String {
+    let coords = crate::format_move((from, to));
+    let (origin, target) = coords.split_at(2);
+    let pawn = piece.kind == PieceType::Pawn;
+    // A pawn moving to another file takes, en passant if the square is empty
+    let capture = board.get_piece(to).is_some() || pawn && from & 7 != to & 7;
+
+    let mut san = piece_letter(piece.kind).to_string();
+    if pawn {
+        if capture {
+            san.push_str(&origin[..1]);
+        }
+    } else {
+        let rivals: Vec<Square> = (0u8..128)
+            .filter(|&sq| {
+                sq != from
+                    && board.get_piece(sq) == Some(piece)
+                    && board.generate_legal_moves_for_piece(sq).contains(&to)
+            })
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|&sq| sq & 7 != from & 7) {
+                san.push_str(&origin[..1]);
+            } else if rivals.iter().all(|&sq| sq >> 4 != from >> 4) {
+                san.push_str(&origin[1..]);
+            } else {
+                san.push_str(origin);
+            }
+        }
+    }
+    if capture {
+        san.push('x');
+    }
+    san.push_str(target);
+    if pawn && matches!(to >> 4, 0 | 7) {
+        san.push_str("=Q");
+    }
+    san
+}
+
+// The legal move `mv` for `color` in algebraic notation, with + or # when
+// it gives check or mate
+pub fn to_san(board: &Board, color: Color, (from, to): (Square, Square)) -> String {
+    let Some(piece) = board.get_piece(from) else {
+        return crate::format_move((from, to));
+    };
+    let mut san = if piece.kind == PieceType::King && from.abs_diff(to) == 2 {
+        if to < from { "O-O-O" } else { "O-O" }.to_string()
+    } else {
+        move_text(board, piece, from, to)
+    };
+
+    let mut after = board.clone();
+    after.make_move(from, to);
+    let opponent = color.opposite();
+    if is_in_check(&after, opponent) {
+        san.push(if has_legal_moves(&after, opponent) { '+' } else { '#' });
+    }
+    san
+}
+
+// A line of moves from the position, each in algebraic notation
+pub fn to_san_line(board: &Board, color: Color, moves: &[(Square, Square)]) -> Vec<String> {
+    let mut board = board.clone();
+    let mut color = color;
+    let mut line = Vec::with_capacity(moves.len());
+    for &(from, to) in moves {
+        line.push(to_san(&board, color, (from, to)));
+        board.make_move(from, to);
+        color = color.opposite();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> (Board, Color) {
+        Board::from_fen(fen).unwrap()
+    }
+
+    fn square(coords: &str) -> Square {
+        crate::coords_to_square(coords).unwrap()
+    }
+
+    fn mv(text: &str) -> (Square, Square) {
+        (square(&text[..2]), square(&text[2..]))
+    }
+
+    #[test]
+    fn reads_the_notation_apart() {
+        assert!(parse_notation("O-O+") == Ok(Notation::Castle { long: false }));
+        assert!(parse_notation("0-0-0") == Ok(Notation::Castle { long: true }));
+        let e8q = Notation::Move {
+            kind: PieceType::Pawn,
+            file: Some(4),
+            row: None,
+            to: square("e8"),
+            promotion: Some(PieceType::Queen),
+        };
+        assert!(parse_notation("e8=Q#") == Ok(e8q));
+        assert!(parse_notation("e8Q") == Ok(e8q));
+        assert_eq!(parse_notation("Nz3").err(), Some(SanError::Invalid));
+        assert_eq!(parse_notation("e2e4").err(), Some(SanError::Invalid));
+    }
+
+    #[test]
+    fn finds_moves_on_the_board() {
+        let (board, color) = position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(parse(&board, color, "e4"), Ok(mv("e2e4")));
+        assert_eq!(parse(&board, color, "Nf3!?"), Ok(mv("g1f3")));
+        assert_eq!(parse(&board, color, "Nxf3"), Ok(mv("g1f3")));
+        assert_eq!(parse(&board, color, "e5"), Err(SanError::Illegal));
+        assert_eq!(parse(&board, color, "O-O"), Err(SanError::Illegal));
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_both() {
+        // Knights on b1 and f1 reach d2, rooks on a1 and a5 reach a3
+        let (board, color) = position("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1");
+        assert_eq!(parse(&board, color, "Nd2"), Err(SanError::Ambiguous));
+        assert_eq!(parse(&board, color, "Nbd2"), Ok(mv("b1d2")));
+        assert_eq!(to_san(&board, color, mv("f1d2")), "Nfd2");
+        assert_eq!(parse(&board, color, "R5a3"), Ok(mv("a5a3")));
+        assert_eq!(to_san(&board, color, mv("a1a3")), "R1a3");
+
+        // Queens on c2, e2 and e4 all reach d3
+        let (board, color) = position("7k/8/8/8/4Q3/8/2Q1Q3/4K3 w - - 0 1");
+        assert_eq!(to_san(&board, color, mv("e2d3")), "Qe2d3");
+        assert_eq!(parse(&board, color, "Qe2d3"), Ok(mv("e2d3")));
+        assert_eq!(to_san(&board, color, mv("c2d3")), "Qcd3");
+    }
+
+    #[test]
+    fn marks_check_and_mate() {
+        let (board, color) = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(to_san(&board, color, mv("a1a8")), "Ra8#");
+        let (board, color) = position("6k1/8/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(to_san(&board, color, mv("a1a8")), "Ra8+");
+        assert_eq!(to_san(&board, color, mv("a1a7")), "Ra7");
+    }
+
+    #[test]
+    fn castling_and_en_passant() {
+        let (board, color) = position("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+        assert_eq!(to_san(&board, color, mv("e1g1")), "O-O");
+        assert_eq!(to_san(&board, color, mv("e1c1")), "O-O-O");
+        assert_eq!(parse(&board, color, "O-O-O"), Ok(mv("e1c1")));
+        assert_eq!(to_san(&board, color, mv("e5d6")), "exd6");
+        assert_eq!(parse(&board, color, "exd6"), Ok(mv("e5d6")));
+        assert_eq!(parse(&board, Color::Black, "O-O"), Ok(mv("e8g8")));
+    }
+
+    #[test]
+    fn promotes_only_to_a_queen() {
+        let (board, color) = position("7k/P7/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(parse(&board, color, "a8=Q+"), Ok(mv("a7a8")));
+        assert_eq!(parse(&board, color, "a8"), Ok(mv("a7a8")));
+        assert_eq!(parse(&board, color, "a8=N"), Err(SanError::Unsupported));
+        assert_eq!(to_san(&board, color, mv("a7a8")), "a8=Q+");
+    }
+
+    #[test]
+    fn lines_follow_the_side_to_move() {
+        let (board, color) = position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let line = to_san_line(&board, color, &[mv("e2e4"), mv("e7e5"), mv("g1f3"), mv("b8c6")]);
+        assert_eq!(line, ["e4", "e5", "Nf3", "Nc6"]);
+    }
+}
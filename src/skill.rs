@@ -0,0 +1,137 @@
+// src/skill.rs
+use crate::{PvLine, Square, MAX_DEPTH};
+use rand::Rng;
+
+pub const MAX_SKILL: u32 = 20;
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2000;
+
+// Number of root moves searched so that a weaker level has alternatives to pick from
+pub const SKILL_MULTIPV: usize = 4;
+
+#[derive(Copy, Clone)]
+pub struct Skill {
+    level: u32,
+}
+
+impl Skill {
+    pub fn new(level: u32) -> Self {
+        Self {
+            level: level.min(MAX_SKILL),
+        }
+    }
+
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        Self::new((elo - MIN_ELO) * MAX_SKILL / (MAX_ELO - MIN_ELO))
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    // Full strength plays the plain search result
+    pub fn enabled(&self) -> bool {
+        self.level < MAX_SKILL
+    }
+
+    pub fn depth(&self) -> i32 {
+        1 + (self.level as i32 * (MAX_DEPTH - 1)) / MAX_SKILL as i32
+    }
+
+    pub fn node_limit(&self) -> u64 {
+        (200.0 * 1.4f64.powi(self.level as i32)) as u64
+    }
+
+    // Chance in percent of deliberately playing an inaccuracy
+    fn inaccuracy_chance(&self) -> u32 {
+        (MAX_SKILL - self.level) * 3 / 2
+    }
+
+    // How much worse than the best move an inaccuracy may be, in centipawns
+    fn inaccuracy_margin(&self) -> i64 {
+        50 + (MAX_SKILL - self.level) as i64 * 15
+    }
+
+    // Picks a move among the MultiPV lines (sorted best first). Lower levels
+    // flatten the score differences between the lines and add more random
+    // noise, so weaker moves get picked more often; big blunders only happen
+    // when an inaccuracy is rolled. Full strength always takes the best line.
+    pub fn pick_move(&self, lines: &[PvLine]) -> Option<(Square, Square)> {
+        let first = lines.first()?;
+        if !self.enabled() {
+            return Some(first.best_move);
+        }
+        let top = first.score as i64;
+        let mut rng = rand::thread_rng();
+
+        if rng.gen_range(0..100) < self.inaccuracy_chance() {
+            let margin = self.inaccuracy_margin();
+            let candidates: Vec<&PvLine> = lines
+                .iter()
+                .filter(|line| top - line.score as i64 <= margin)
+                .collect();
+            return Some(candidates[rng.gen_range(0..candidates.len())].best_move);
+        }
+
+        let weakness = 120 - 2 * self.level as i64;
+        let delta = (top - lines.last()?.score as i64).min(100);
+        let mut best = None;
+        let mut best_value = i64::MIN;
+        for line in lines {
+            let gap = (top - line.score as i64).min(1000);
+            let push = (weakness * gap + delta * rng.gen_range(0..weakness)) / 128;
+            let value = (line.score as i64).max(top - 1000) + push;
+            if value >= best_value {
+                best_value = value;
+                best = Some(line.best_move);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines() -> Vec<PvLine> {
+        [(0x64, 0x44, 30), (0x63, 0x43, 20), (0x76, 0x55, 0), (0x60, 0x40, -200)]
+            .into_iter()
+            .map(|(from, to, score)| PvLine {
+                best_move: (from, to),
+                score,
+                pv: vec![(from, to)],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_strength_plays_the_best_line() {
+        let skill = Skill::new(MAX_SKILL);
+        for _ in 0..200 {
+            assert_eq!(skill.pick_move(&lines()), Some((0x64, 0x44)));
+        }
+    }
+
+    #[test]
+    fn the_weakest_level_picks_one_of_the_lines() {
+        let skill = Skill::new(0);
+        let lines = lines();
+        for _ in 0..200 {
+            let mv = skill.pick_move(&lines).unwrap();
+            assert!(lines.iter().any(|line| line.best_move == mv));
+        }
+        assert_eq!(skill.pick_move(&[]), None);
+    }
+
+    #[test]
+    fn elo_maps_onto_the_levels() {
+        assert_eq!(Skill::from_elo(MIN_ELO).level(), 0);
+        assert_eq!(Skill::from_elo(MIN_ELO - 100).level(), 0);
+        assert_eq!(Skill::from_elo(MAX_ELO).level(), MAX_SKILL);
+        assert_eq!(Skill::from_elo(MAX_ELO + 100).level(), MAX_SKILL);
+        assert_eq!(Skill::from_elo((MIN_ELO + MAX_ELO) / 2).level(), MAX_SKILL / 2);
+        assert!(!Skill::from_elo(MAX_ELO).enabled());
+    }
+}
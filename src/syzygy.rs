@@ -0,0 +1,1236 @@
+// src/syzygy.rs
+//
+// Probing of Syzygy endgame tablebases. WDL tables (.rtbw) give the result
+// of a position under the fifty-move rule, DTZ tables (.rtbz) the distance
+// to the next capture or pawn move (zeroing move) that keeps the result.
+//
+// Tables are looked up by their material ("KRPvKR") in the directories set
+// with `init` and memory-mapped the first time they are needed. Positions
+// the tables do not cover, or whose files are missing, probe as None so the
+// search carries on without them.
+//
+// The file format and the decoding follow the reference implementation by
+// Ronald de Man as found in Stockfish: positions are mapped to an index by
+// removing the board symmetries, and the index is looked up in blocks of
+// symbols compressed with recursive pairing and a canonical Huffman code.
+//
+// The engine itself only promotes to a queen. The captures searched while
+// probing promote to every piece, as the tables expect, and take en
+// passant. Positions with castling rights are not in the tables.
+use crate::pawns::relative_rank;
+use crate::tablebase;
+use crate::{is_in_check, Board, Color, Piece, PieceType, Square};
+use lazy_static::lazy_static;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+// Score of a won tablebase position: above any evaluation, below mate scores
+pub const TB_WIN: i32 = 20000;
+
+// Largest tables in the Syzygy format
+const TB_PIECES: usize = 7;
+const MAX_DTZ: i32 = 1 << 18;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Table flags, first byte of the file
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// Flags of a compressed sub-table
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Result for the side to move, cursed wins and blessed losses being the
+// ones the fifty-move rule turns into draws
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    // Search score `ply` plies from the root, wins reached sooner scoring
+    // higher; results the fifty-move rule decides count as draws
+    pub fn score(self, ply: i32) -> i32 {
+        match self {
+            Wdl::Win => TB_WIN - ply,
+            Wdl::Loss => -TB_WIN + ply,
+            _ => 0,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TableType {
+    Wdl,
+    Dtz,
+}
+
+// Index tables shared by all tablebases; squares are numbered a1 = 0 .. h8 = 63
+struct Indexing {
+    binomial: [[u64; 64]; TB_PIECES],
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+    map_b1h1h7: [u64; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+}
+
+fn file_of(sq: usize) -> usize {
+    sq & 7
+}
+
+fn rank_of(sq: usize) -> usize {
+    sq >> 3
+}
+
+// Positive above the a1-h8 diagonal, negative below
+fn off_a1h8(sq: usize) -> i32 {
+    rank_of(sq) as i32 - file_of(sq) as i32
+}
+
+fn sq_distance(a: usize, b: usize) -> usize {
+    file_of(a).abs_diff(file_of(b)).max(rank_of(a).abs_diff(rank_of(b)))
+}
+
+impl Indexing {
+    fn new() -> Self {
+        let mut ix = Indexing {
+            binomial: [[0; 64]; TB_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        // Squares below the a1-h8 diagonal, 0..27
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                ix.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        // The a1-d1-d4 triangle, 0..9 with the diagonal last
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in 0..=27 {
+            if off_a1h8(sq) < 0 && file_of(sq) <= 3 {
+                ix.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && file_of(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            ix.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // The 462 legal placements of two kings with the first one in the
+        // triangle; if it is on the diagonal, the second one is not above it
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                // b1 is mapped to 0, as are the squares outside the triangle
+                if ix.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    if sq_distance(s1, s2) <= 1 || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                        continue;
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        ix.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            ix.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        ix.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..TB_PIECES.min(n + 1) {
+                ix.binomial[k][n] = if k > 0 { ix.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { ix.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // a2-h7 to 0..47, higher for squares nearer the edge and, on the same
+        // file, lower ranks. The pawn with the highest value leads.
+        let mut available = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        ix.map_pawns[sq] = available;
+                        ix.map_pawns[sq ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    ix.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += ix.binomial[lead_pawns - 1][ix.map_pawns[sq]];
+                }
+                ix.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        ix
+    }
+}
+
+// One compressed table: a side to move (WDL) and a file of the leading pawn
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; TB_PIECES],
+    group_len: [usize; TB_PIECES + 1],
+    group_idx: [u64; TB_PIECES + 1],
+    sizeof_block: u64,
+    span: u64,
+    sparse_index_size: usize,
+    blocks_num: usize,
+    block_length_size: usize,
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    // DTZ value maps by result, offsets into the table's map
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    kind: TableType,
+    mmap: Mmap,
+    // Piece counts [color][piece type] with the first side of the name as White
+    material: [[u8; 6]; 2],
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [usize; 2],
+    piece_count: usize,
+    // [side to move][file of the leading pawn]
+    pairs: Vec<Vec<PairsData>>,
+    map: usize,
+}
+
+// Little-endian reads that fail instead of panicking on truncated files
+fn read_u8(data: &[u8], pos: usize) -> Option<u8> {
+    data.get(pos).copied()
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// Compressed data is read big-endian; reads past the end give zeros
+fn read_be32(data: &[u8], pos: usize) -> u32 {
+    data.get(pos..pos + 4).map_or(0, |b| u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+// Children of a pairing symbol, 12 bits each
+fn btree_left(data: &[u8], btree: usize, sym: usize) -> Option<usize> {
+    let lr = data.get(btree + 3 * sym..btree + 3 * sym + 3)?;
+    Some(((lr[1] as usize & 0xF) << 8) | lr[0] as usize)
+}
+
+fn btree_right(data: &[u8], btree: usize, sym: usize) -> Option<usize> {
+    let lr = data.get(btree + 3 * sym..btree + 3 * sym + 3)?;
+    Some(((lr[2] as usize) << 4) | (lr[1] as usize >> 4))
+}
+
+// Number of values a symbol expands to, minus one
+fn set_symlen(d: &mut PairsData, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+    let right = btree_right(data, d.btree, sym)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+    let left = btree_left(data, d.btree, sym)?;
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            d.symlen[child] = set_symlen(d, data, child, visited)?;
+        }
+    }
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+fn parse_name(name: &str) -> Option<[[u8; 6]; 2]> {
+    let (white, black) = name.split_once('v')?;
+    let mut material = [[0u8; 6]; 2];
+    for (side, pieces) in [white, black].iter().enumerate() {
+        for c in pieces.chars() {
+            let kind = match c {
+                'P' => PieceType::Pawn,
+                'N' => PieceType::Knight,
+                'B' => PieceType::Bishop,
+                'R' => PieceType::Rook,
+                'Q' => PieceType::Queen,
+                'K' => PieceType::King,
+                _ => return None,
+            };
+            material[side][kind as usize] += 1;
+        }
+    }
+    (material[0][5] == 1 && material[1][5] == 1).then_some(material)
+}
+
+impl Table {
+    fn open(mmap: Mmap, kind: TableType, name: &str) -> Option<Table> {
+        let material = parse_name(name)?;
+
+        let white_pawns = material[0][0] as usize;
+        let black_pawns = material[1][0] as usize;
+        // With pawns on both sides the side with fewer pawns leads, as it
+        // compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            kind,
+            mmap,
+            material,
+            symmetric: material[0] == material[1],
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: material.iter().any(|side| side[..5].contains(&1)),
+            pawn_count: if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] },
+            piece_count: material.iter().flatten().map(|&n| n as usize).sum(),
+            pairs: Vec::new(),
+            map: 0,
+        };
+        if table.piece_count > TB_PIECES {
+            return None;
+        }
+        table.parse()?;
+        Some(table)
+    }
+
+    fn parse(&mut self) -> Option<()> {
+        let data: &[u8] = &self.mmap;
+        let magic = if self.kind == TableType::Wdl { WDL_MAGIC } else { DTZ_MAGIC };
+        if data.get(..4)? != magic {
+            return None;
+        }
+        let flags = read_u8(data, 4)?;
+        let split_mismatch = self.kind == TableType::Wdl && (flags & SPLIT != 0) == self.symmetric;
+        if (flags & HAS_PAWNS != 0) != self.has_pawns || split_mismatch {
+            return None;
+        }
+        let mut pos = 5;
+
+        let sides = if self.kind == TableType::Wdl && !self.symmetric { 2 } else { 1 };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+
+        for f in 0..files {
+            let first = read_u8(data, pos)?;
+            let second = if both_pawns { read_u8(data, pos + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            pos += 1 + both_pawns as usize;
+
+            for k in 0..self.piece_count {
+                let byte = read_u8(data, pos)?;
+                for (i, side) in pairs.iter_mut().enumerate() {
+                    side[f].pieces[k] = if i == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                pos += 1;
+            }
+            for (i, side) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut side[f], order[i], f);
+            }
+        }
+        pos += pos & 1;
+
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                pos = set_sizes(&mut side[f], data, pos)?;
+            }
+        }
+
+        if self.kind == TableType::Dtz {
+            self.map = pos;
+            for d in pairs[0].iter_mut() {
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - self.map) / 2 + 1;
+                        pos += 2 * read_u16(data, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = pos - self.map + 1;
+                        pos += read_u8(data, pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].sparse_index = pos;
+                pos += side[f].sparse_index_size * 6;
+            }
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].block_length = pos;
+                pos += side[f].block_length_size * 2;
+            }
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                pos = (pos + 0x3F) & !0x3F;
+                side[f].data = pos;
+                pos += side[f].blocks_num * side[f].sizeof_block as usize;
+            }
+        }
+        if pos > data.len() {
+            return None;
+        }
+
+        self.pairs = pairs;
+        Some(())
+    }
+
+    // Splits the pieces into groups of identical pieces (the first group is
+    // the leading pawns, or the kings and maybe one more unique piece) and
+    // computes the index factor of every group. `order` gives the position
+    // of the leading group and of the other side's pawns in that sequence.
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], f: usize) {
+        let ix = &*INDEXING;
+        let mut n = 0;
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0u8;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    ix.lead_pawns_size[d.group_len[0]][f]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= ix.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= ix.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.pairs[stm % self.pairs.len()][if self.has_pawns { file } else { 0 }]
+    }
+}
+
+// Reads the decoding tables of one compressed table, returning the position after them
+fn set_sizes(d: &mut PairsData, data: &[u8], mut pos: usize) -> Option<usize> {
+    d.flags = read_u8(data, pos)?;
+    pos += 1;
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        // The one value of the table is kept in min_sym_len
+        d.min_sym_len = read_u8(data, pos)?;
+        return Some(pos + 1);
+    }
+
+    let end = d.group_len.iter().position(|&len| len == 0).unwrap_or(TB_PIECES);
+    let tb_size = d.group_idx[end];
+
+    d.sizeof_block = 1u64 << read_u8(data, pos)?;
+    d.span = 1u64 << read_u8(data, pos + 1)?;
+    d.sparse_index_size = tb_size.div_ceil(d.span) as usize;
+    let padding = read_u8(data, pos + 2)? as usize;
+    d.blocks_num = read_u32(data, pos + 3)? as usize;
+    // Padded so that the sparse index never points past the end
+    d.block_length_size = d.blocks_num + padding;
+    let max_sym_len = read_u8(data, pos + 7)?;
+    d.min_sym_len = read_u8(data, pos + 8)?;
+    pos += 9;
+    if max_sym_len < d.min_sym_len || max_sym_len > 64 {
+        return None;
+    }
+    d.lowest_sym = pos;
+
+    // Canonical Huffman code: base64[i] is the lowest code of length
+    // min_sym_len + i, left-aligned in 64 bits, so longer codes come lower
+    let lengths = (max_sym_len - d.min_sym_len) as usize + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16(data, d.lowest_sym + 2 * i)? as u64;
+        let next_lowest = read_u16(data, d.lowest_sym + 2 * (i + 1))? as u64;
+        d.base64[i] = (d.base64[i + 1] + lowest).checked_sub(next_lowest)? / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl((64 - i - d.min_sym_len as usize) as u32).unwrap_or(0);
+    }
+    pos += 2 * lengths;
+
+    let symbols = read_u16(data, pos)? as usize;
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, data, sym, &mut visited)?;
+        }
+    }
+    Some(pos + 3 * symbols + (symbols & 1))
+}
+
+// The value stored at `idx`
+fn decompress_pairs(d: &PairsData, data: &[u8], idx: u64) -> Option<i32> {
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        return Some(d.min_sym_len as i32);
+    }
+
+    // The sparse index entry k points to the block holding value
+    // k * span + span / 2 and its offset in that block
+    let k = (idx / d.span) as usize;
+    let entry = d.sparse_index + 6 * k;
+    let mut block = read_u32(data, entry)? as usize;
+    let mut offset = read_u16(data, entry + 4)? as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+    // Each block holds block_length + 1 values
+    let block_length = |block: usize| read_u16(data, d.block_length + 2 * block).map(|len| len as i64);
+    while offset < 0 {
+        block = block.checked_sub(1)?;
+        offset += block_length(block)? + 1;
+    }
+    while offset > block_length(block)? {
+        offset -= block_length(block)? + 1;
+        block += 1;
+    }
+
+    let mut ptr = d.data + block * d.sizeof_block as usize;
+    let mut buf64 = ((read_be32(data, ptr) as u64) << 32) | read_be32(data, ptr + 4) as u64;
+    ptr += 8;
+    let mut buf64_size = 64;
+    let min_sym_len = d.min_sym_len as usize;
+
+    let mut sym;
+    loop {
+        let mut len = 0;
+        while len + 1 < d.base64.len() && buf64 < d.base64[len] {
+            len += 1;
+        }
+        sym = ((buf64 - d.base64[len]) >> (64 - len - min_sym_len)) as usize;
+        sym += read_u16(data, d.lowest_sym + 2 * len)? as usize;
+
+        let count = *d.symlen.get(sym)? as i64 + 1;
+        if offset < count {
+            break;
+        }
+        offset -= count;
+        let len = len + min_sym_len;
+        buf64 = buf64.checked_shl(len as u32).unwrap_or(0);
+        buf64_size -= len as i32;
+        if buf64_size <= 32 {
+            buf64_size += 32;
+            buf64 |= (read_be32(data, ptr) as u64) << (64 - buf64_size);
+            ptr += 4;
+        }
+    }
+
+    // Expand the pairs down to the single value at our offset
+    while d.symlen[sym] != 0 {
+        let left = btree_left(data, d.btree, sym)?;
+        let left_count = *d.symlen.get(left)? as i64 + 1;
+        if offset < left_count {
+            sym = left;
+        } else {
+            offset -= left_count;
+            sym = btree_right(data, d.btree, sym)?;
+        }
+    }
+    btree_left(data, d.btree, sym).map(|value| value as i32)
+}
+
+// What a table lookup found
+enum Probe {
+    Value(i32),
+    // A DTZ table only holds one side to move, and it is the other one
+    OtherSide,
+}
+
+fn piece_code(piece: Piece) -> u8 {
+    ((piece.color as u8) << 3) | (piece.kind as u8 + 1)
+}
+
+fn do_probe_table(table: &Table, board: &Board, color: Color, wdl: Wdl) -> Option<Probe> {
+    let ix = &*INDEXING;
+    let data: &[u8] = &table.mmap;
+
+    // Pieces in square order, as the reference implementation sees them
+    let mut position = Vec::with_capacity(TB_PIECES);
+    let mut white = [0u8; 6];
+    for tb_sq in 0..64 {
+        let sq = ((7 - tb_sq / 8) * 16 + tb_sq % 8) as Square;
+        if let Some(piece) = board.get_piece(sq) {
+            position.push((tb_sq, piece_code(piece)));
+            if piece.color == Color::White {
+                white[piece.kind as usize] += 1;
+            }
+        }
+    }
+
+    // Tables have the side named first as White and only the white to move
+    // half if both sides have the same pieces; other positions are mirrored
+    let flip = (color == Color::Black && table.symmetric) || white != table.material[0];
+    let flip_color = if flip { 8 } else { 0 };
+    let flip_squares = if flip { 56 } else { 0 };
+    let stm = flip as usize ^ color as usize;
+
+    let mut squares = [0usize; TB_PIECES];
+    let mut pieces = [0u8; TB_PIECES];
+    let mut size = 0;
+    let mut lead_pawns = 0;
+    let mut tb_file = 0;
+    let mut lead_code = None;
+
+    // The leading pawn is the one with the highest map_pawns value, the
+    // one nearest the edge and lowest; its file selects the table
+    if table.has_pawns {
+        let code = table.pairs[0][0].pieces[0] ^ flip_color;
+        for &(sq, _) in position.iter().filter(|&&(_, c)| c == code) {
+            squares[size] = sq ^ flip_squares;
+            size += 1;
+        }
+        lead_pawns = size;
+        let lead = (0..lead_pawns).rev().max_by_key(|&i| ix.map_pawns[squares[i]])?;
+        squares.swap(0, lead);
+        tb_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        lead_code = Some(code);
+    }
+
+    if table.kind == TableType::Dtz {
+        let d = table.get(stm, tb_file);
+        // Symmetric tables without pawns are the same for both sides
+        if (table.has_pawns || !table.symmetric) && (d.flags & FLAG_STM) as usize != stm {
+            return Some(Probe::OtherSide);
+        }
+    }
+
+    for &(sq, code) in &position {
+        if Some(code) != lead_code {
+            squares[size] = sq ^ flip_squares;
+            pieces[size] = code ^ flip_color;
+            size += 1;
+        }
+    }
+    let d = table.get(stm, tb_file);
+
+    // Order the pieces as the table does
+    for i in lead_pawns..size.saturating_sub(1) {
+        if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    // The leading piece goes to the a-d files
+    if file_of(squares[0]) > 3 {
+        squares[..size].iter_mut().for_each(|sq| *sq ^= 7);
+    }
+
+    let mut idx;
+    if table.has_pawns {
+        idx = ix.lead_pawn_idx[lead_pawns][squares[0]];
+        squares[1..lead_pawns].sort_by_key(|&sq| ix.map_pawns[sq]);
+        for (i, &sq) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+            idx += ix.binomial[i][ix.map_pawns[sq]];
+        }
+    } else {
+        // Without pawns, also to ranks 1-4 and below the a1-h8 diagonal
+        if rank_of(squares[0]) > 3 {
+            squares[..size].iter_mut().for_each(|sq| *sq ^= 56);
+        }
+        for i in 0..d.group_len[0] {
+            let off = off_a1h8(squares[i]);
+            if off == 0 {
+                continue;
+            }
+            if off > 0 {
+                for sq in squares[i..size].iter_mut() {
+                    *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                }
+            }
+            break;
+        }
+
+        if table.has_unique_pieces {
+            let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+            let adjust1 = (s1 > s0) as usize;
+            let adjust2 = (s2 > s0) as usize + (s2 > s1) as usize;
+            idx = if off_a1h8(s0) != 0 {
+                ((ix.map_a1d1d4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2) as u64
+            } else if off_a1h8(s1) != 0 {
+                ((6 * 63 + rank_of(s0) as u64 * 28 + ix.map_b1h1h7[s1]) * 62) + (s2 - adjust2) as u64
+            } else if off_a1h8(s2) != 0 {
+                (6 * 63 * 62 + 4 * 28 * 62 + rank_of(s0) * 7 * 28 + (rank_of(s1) - adjust1) * 28) as u64
+                    + ix.map_b1h1h7[s2]
+            } else {
+                (6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank_of(s0) * 7 * 6
+                    + (rank_of(s1) - adjust1) * 6
+                    + (rank_of(s2) - adjust2)) as u64
+            };
+        } else {
+            idx = ix.map_kk[ix.map_a1d1d4[squares[0]]][squares[1]];
+        }
+    }
+
+    // The remaining groups, each as a combination of the free squares
+    idx *= d.group_idx[0];
+    let mut group_start = d.group_len[0];
+    let mut remaining_pawns = table.has_pawns && table.pawn_count[1] > 0;
+    let mut next = 1;
+    while d.group_len[next] != 0 {
+        let len = d.group_len[next];
+        squares[group_start..group_start + len].sort_unstable();
+        let mut n = 0;
+        for i in 0..len {
+            let sq = squares[group_start + i];
+            let adjust = squares[..group_start].iter().filter(|&&s| sq > s).count();
+            n += ix.binomial[i + 1][(sq - adjust).checked_sub(8 * remaining_pawns as usize)?];
+        }
+        remaining_pawns = false;
+        idx += n * d.group_idx[next];
+        group_start += len;
+        next += 1;
+    }
+
+    let value = decompress_pairs(d, data, idx)?;
+    Some(Probe::Value(match table.kind {
+        TableType::Wdl => value - 2,
+        TableType::Dtz => map_dtz(table, d, value, wdl)?,
+    }))
+}
+
+// DTZ values may be stored through a map and in moves instead of plies
+fn map_dtz(table: &Table, d: &PairsData, mut value: i32, wdl: Wdl) -> Option<i32> {
+    const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+    let data: &[u8] = &table.mmap;
+    if d.flags & FLAG_MAPPED != 0 {
+        let idx = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]] + value as usize;
+        value = if d.flags & FLAG_WIDE != 0 {
+            read_u16(data, table.map + 2 * idx)? as i32
+        } else {
+            read_u8(data, table.map + idx)? as i32
+        };
+    }
+    let in_moves = match wdl {
+        Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+        Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+        Wdl::CursedWin | Wdl::BlessedLoss => true,
+        Wdl::Draw => false,
+    };
+    Some(if in_moves { value * 2 + 1 } else { value + 1 })
+}
+
+struct Tablebases {
+    paths: Vec<PathBuf>,
+    wdl: HashMap<String, Option<Arc<Table>>>,
+    dtz: HashMap<String, Option<Arc<Table>>>,
+}
+
+lazy_static! {
+    static ref INDEXING: Indexing = Indexing::new();
+    static ref TABLEBASES: RwLock<Tablebases> = RwLock::new(Tablebases {
+        paths: Vec::new(),
+        wdl: HashMap::new(),
+        dtz: HashMap::new(),
+    });
+}
+
+// Pieces in the largest table found, 0 without tablebases
+static MAX_PIECES: AtomicUsize = AtomicUsize::new(0);
+
+// Uses the tables in the directories of `paths` (see
+// tablebase::directories). Returns the number of WDL tables found.
+pub fn init(paths: &str) -> usize {
+    let mut tablebases = TABLEBASES.write().unwrap();
+    tablebases.wdl.clear();
+    tablebases.dtz.clear();
+    tablebases.paths.clear();
+    MAX_PIECES.store(0, Ordering::SeqCst);
+
+    let mut found = 0;
+    let mut max_pieces = 0;
+    for dir in tablebase::directories(paths) {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(|n| n.strip_suffix(".rtbw")) else { continue };
+            if let Some(material) = parse_name(name) {
+                let pieces: usize = material.iter().flatten().map(|&n| n as usize).sum();
+                max_pieces = max_pieces.max(pieces);
+                found += 1;
+            }
+        }
+        tablebases.paths.push(dir);
+    }
+    MAX_PIECES.store(max_pieces.min(TB_PIECES), Ordering::SeqCst);
+    found
+}
+
+pub fn max_pieces() -> usize {
+    MAX_PIECES.load(Ordering::Relaxed)
+}
+
+fn material_name(board: &Board, color: Color) -> String {
+    let mut name = String::from("K");
+    for (kind, c) in [
+        (PieceType::Queen, 'Q'),
+        (PieceType::Rook, 'R'),
+        (PieceType::Bishop, 'B'),
+        (PieceType::Knight, 'N'),
+        (PieceType::Pawn, 'P'),
+    ] {
+        for sq in 0u8..128 {
+            if matches!(board.get_piece(sq), Some(p) if p.color == color && p.kind == kind) {
+                name.push(c);
+            }
+        }
+    }
+    name
+}
+
+// The table for this position's material, loading it on first use
+fn table_for(board: &Board, kind: TableType) -> Option<Arc<Table>> {
+    let white = material_name(board, Color::White);
+    let black = material_name(board, Color::Black);
+    let names = [format!("{}v{}", white, black), format!("{}v{}", black, white)];
+    let extension = if kind == TableType::Wdl { "rtbw" } else { "rtbz" };
+
+    {
+        let tablebases = TABLEBASES.read().unwrap();
+        let cache = if kind == TableType::Wdl { &tablebases.wdl } else { &tablebases.dtz };
+        if let Some(table) = names.iter().find_map(|name| cache.get(name)) {
+            return table.clone();
+        }
+    }
+
+    let mut tablebases = TABLEBASES.write().unwrap();
+    let mut loaded = None;
+    'search: for name in &names {
+        for dir in &tablebases.paths {
+            let path = dir.join(format!("{}.{}", name, extension));
+            if !path.exists() {
+                continue;
+            }
+            loaded = tablebase::load(&path, |mmap| Table::open(mmap, kind, name)).map(Arc::new);
+            break 'search;
+        }
+    }
+    // Remember missing tables too, so the directories are only searched once
+    let cache = if kind == TableType::Wdl { &mut tablebases.wdl } else { &mut tablebases.dtz };
+    for name in &names {
+        cache.insert(name.clone(), loaded.clone());
+    }
+    loaded
+}
+
+fn probe_table(board: &Board, color: Color, kind: TableType, wdl: Wdl) -> Option<Probe> {
+    let pieces = (0u8..128).filter(|&sq| board.get_piece(sq).is_some()).count();
+    if pieces == 2 {
+        return Some(Probe::Value(0)); // KvK
+    }
+    do_probe_table(&*table_for(board, kind)?, board, color, wdl)
+}
+
+fn probe_wdl_table(board: &Board, color: Color) -> Option<Wdl> {
+    match probe_table(board, color, TableType::Wdl, Wdl::Draw)? {
+        Probe::Value(value) => Some(Wdl::from_value(value)),
+        Probe::OtherSide => None,
+    }
+}
+
+struct Child {
+    board: Board,
+    capture: bool,
+    pawn_move: bool,
+}
+
+// Legal moves as the positions they lead to; pawns reaching the last rank
+// promote to each piece
+fn children(board: &Board, color: Color) -> Vec<Child> {
+    let mut children = Vec::new();
+    for from in 0u8..128 {
+        let Some(piece) = board.get_piece(from) else { continue };
+        if piece.color != color {
+            continue;
+        }
+        for to in board.generate_legal_moves_for_piece(from) {
+            let pawn_move = piece.kind == PieceType::Pawn;
+            let capture = board.get_piece(to).is_some() || pawn_move && from & 7 != to & 7;
+            let mut child = board.clone();
+            child.make_move(from, to);
+            if pawn_move && relative_rank(to, color) == 7 {
+                for kind in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                    let mut promoted = child.clone();
+                    promoted.set_piece(to, Piece { color, kind });
+                    children.push(Child { board: promoted, capture, pawn_move });
+                }
+            } else {
+                children.push(Child { board: child, capture, pawn_move });
+            }
+        }
+    }
+    children
+}
+
+fn has_legal_moves(board: &Board, color: Color) -> bool {
+    (0u8..128).any(|from| {
+        matches!(board.get_piece(from), Some(p) if p.color == color)
+            && !board.generate_legal_moves_for_piece(from).is_empty()
+    })
+}
+
+// Searches the captures (and, for DTZ, the pawn moves) before probing: the
+// tables store a "don't care" value where the best move zeroes the
+// counter. Also returns whether the best move is such a zeroing move.
+fn search(board: &Board, color: Color, check_zeroing: bool) -> Option<(Wdl, bool)> {
+    let moves = children(board, color);
+    let mut best = Wdl::Loss;
+    let mut searched = 0;
+    for child in &moves {
+        if !(child.capture || (check_zeroing && child.pawn_move)) {
+            continue;
+        }
+        searched += 1;
+        let value = -search(&child.board, color.opposite(), false)?.0;
+        if value > best {
+            best = value;
+            if value >= Wdl::Win {
+                return Some((value, true));
+            }
+        }
+    }
+
+    // With every move searched the table value is not needed
+    let no_more_moves = searched > 0 && searched == moves.len();
+    let value = if no_more_moves { best } else { probe_wdl_table(board, color)? };
+    if best >= value {
+        return Some((best, best > Wdl::Draw || no_more_moves));
+    }
+    Some((value, false))
+}
+
+// Only positions without castling rights or pawns on the last rank, and
+// within the size of the tables found, can be probed
+fn probeable(board: &Board) -> bool {
+    let max = max_pieces();
+    if max == 0 || board.castling.contains(&true) {
+        return false;
+    }
+    let mut pieces = 0;
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq) {
+            pieces += 1;
+            if piece.kind == PieceType::Pawn && (sq >> 4 == 0 || sq >> 4 == 7) {
+                return false;
+            }
+        }
+    }
+    pieces <= max
+}
+
+// Win, draw or loss for `color`, to move
+pub fn probe_wdl(board: &Board, color: Color) -> Option<Wdl> {
+    if !probeable(board) {
+        return None;
+    }
+    search(board, color, false).map(|(wdl, _)| wdl)
+}
+
+// DTZ of the move before a zeroing move with this result
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn dtz(board: &Board, color: Color) -> Option<i32> {
+    let (wdl, zeroing) = search(board, color, true)?;
+    if wdl == Wdl::Draw {
+        return Some(0);
+    }
+    if zeroing {
+        return Some(dtz_before_zeroing(wdl));
+    }
+
+    let sign = (wdl as i32).signum();
+    match probe_table(board, color, TableType::Dtz, wdl)? {
+        Probe::Value(value) => {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            Some((value + if cursed { 100 } else { 0 }) * sign)
+        }
+        // One ply deeper the table has the side to move; take the best
+        // move for our result
+        Probe::OtherSide => {
+            let mut min_dtz = 0xFFFF;
+            for child in children(board, color) {
+                let zeroing = child.capture || child.pawn_move;
+                let opponent = color.opposite();
+                let mut value = if zeroing {
+                    -dtz_before_zeroing(search(&child.board, opponent, false)?.0)
+                } else {
+                    -dtz(&child.board, opponent)?
+                };
+                if value == 1 && is_in_check(&child.board, opponent) && !has_legal_moves(&child.board, opponent) {
+                    min_dtz = 1;
+                }
+                if !zeroing {
+                    value += value.signum();
+                }
+                if value < min_dtz && value.signum() == sign {
+                    min_dtz = value;
+                }
+            }
+            Some(if min_dtz == 0xFFFF { -1 } else { min_dtz })
+        }
+    }
+}
+
+// Plies to the next zeroing move with best play: positive if `color`, to
+// move, wins, negative if it loses, 0 for a draw. Over 100 the win or loss
+// comes too late for the fifty-move rule.
+pub fn probe_dtz(board: &Board, color: Color) -> Option<i32> {
+    if !probeable(board) {
+        return None;
+    }
+    dtz(board, color)
+}
+
+// DTZ of a move from the DTZ of the position after it, which the opponent
+// is to move in: one ply further from the zeroing move, sign flipped
+fn dtz_before(dtz_after: i32) -> i32 {
+    -dtz_after - dtz_after.signum()
+}
+
+// Orders moves by their DTZ: quicker wins first, then draws, then the
+// losses that take longest
+fn rank(dtz: i32) -> i32 {
+    match dtz {
+        d if d > 0 => MAX_DTZ - d,
+        d if d < 0 => -MAX_DTZ - d,
+        _ => 0,
+    }
+}
+
+// The root moves that keep the best tablebase result: the wins with the
+// lowest DTZ, all drawing moves, or the losses that hold out the longest.
+// Moves to a position that cannot be probed are kept for the search to
+// judge. None if the position itself cannot be probed, or none of the
+// positions after a move.
+pub fn root_moves(board: &Board, color: Color) -> Option<Vec<(Square, Square)>> {
+    if !probeable(board) {
+        return None;
+    }
+    let opponent = color.opposite();
+    let mut ranked = Vec::new();
+    let mut unknown = Vec::new();
+    for from in 0u8..128 {
+        let Some(piece) = board.get_piece(from) else { continue };
+        if piece.color != color {
+            continue;
+        }
+        for to in board.generate_legal_moves_for_piece(from) {
+            let zeroing = board.get_piece(to).is_some() || piece.kind == PieceType::Pawn;
+            let mut child = board.clone();
+            child.make_move(from, to);
+            let dtz = if !has_legal_moves(&child, opponent) {
+                Some(if is_in_check(&child, opponent) { 1 } else { 0 })
+            } else if zeroing {
+                probe_wdl(&child, opponent).map(|wdl| dtz_before_zeroing(-wdl))
+            } else {
+                probe_dtz(&child, opponent).map(dtz_before)
+            };
+            let Some(dtz) = dtz else {
+                unknown.push((from, to));
+                continue;
+            };
+            ranked.push(((from, to), rank(dtz)));
+        }
+    }
+    let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+    let mut moves: Vec<_> = ranked.into_iter().filter(|&(_, rank)| rank == best).map(|(mv, _)| mv).collect();
+    moves.extend(unknown);
+    Some(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wdl_scores_count_from_the_root() {
+        assert_eq!(Wdl::Win.score(3), TB_WIN - 3);
+        assert_eq!(Wdl::Loss.score(3), -TB_WIN + 3);
+        for wdl in [Wdl::CursedWin, Wdl::Draw, Wdl::BlessedLoss] {
+            assert_eq!(wdl.score(3), 0);
+        }
+    }
+
+    #[test]
+    fn negation_swaps_the_sides() {
+        assert_eq!(-Wdl::Win, Wdl::Loss);
+        assert_eq!(-Wdl::CursedWin, Wdl::BlessedLoss);
+        assert_eq!(-Wdl::Draw, Wdl::Draw);
+        assert_eq!(-Wdl::BlessedLoss, Wdl::CursedWin);
+        assert_eq!(-Wdl::Loss, Wdl::Win);
+    }
+
+    #[test]
+    fn zeroing_moves_are_one_ply_from_their_result() {
+        assert_eq!(dtz_before_zeroing(Wdl::Win), 1);
+        assert_eq!(dtz_before_zeroing(Wdl::Loss), -1);
+        assert_eq!(dtz_before_zeroing(Wdl::Draw), 0);
+        // Past the fifty-move rule
+        assert_eq!(dtz_before_zeroing(Wdl::CursedWin), 101);
+        assert_eq!(dtz_before_zeroing(Wdl::BlessedLoss), -101);
+    }
+
+    #[test]
+    fn a_move_adds_a_ply_and_flips_the_sign() {
+        // The opponent loses 5 plies after our move: we win in 6
+        assert_eq!(dtz_before(-5), 6);
+        assert_eq!(dtz_before(5), -6);
+        assert_eq!(dtz_before(0), 0);
+    }
+
+    #[test]
+    fn quick_wins_rank_first_and_long_losses_before_short_ones() {
+        let order = [1, 2, 100, 101, 0, -101, -100, -2, -1];
+        for pair in order.windows(2) {
+            assert!(rank(pair[0]) > rank(pair[1]), "{} should rank above {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn table_names() {
+        let material = parse_name("KRPvKN").unwrap();
+        assert_eq!(material[0], [1, 0, 0, 1, 0, 1]);
+        assert_eq!(material[1], [0, 1, 0, 0, 0, 1]);
+        assert!(parse_name("KRvR").is_none());
+        assert!(parse_name("KXvK").is_none());
+    }
+
+    #[test]
+    fn nothing_is_probed_without_tables() {
+        let (board, color) = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(max_pieces(), 0);
+        assert!(probe_wdl(&board, color).is_none());
+        assert!(probe_dtz(&board, color).is_none());
+        assert!(root_moves(&board, color).is_none());
+    }
+
+    // Needs the KRvK tables in the directories of SYZYGY_PATH
+    #[test]
+    #[ignore]
+    fn krvk_from_syzygy_path() {
+        let paths = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH names the table directories");
+        assert!(init(&paths) > 0);
+        let (board, color) = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(probe_wdl(&board, color), Some(Wdl::Win));
+        assert_eq!(probe_wdl(&board, Color::Black), Some(Wdl::Loss));
+        assert!(probe_dtz(&board, color).is_some_and(|dtz| dtz > 0 && dtz <= 100));
+        // The rook must not be left hanging
+        let (board, color) = Board::from_fen("8/8/8/8/8/8/1k6/R3K3 b - - 0 1").unwrap();
+        assert_eq!(root_moves(&board, color), Some(vec![(0x61, 0x70)]));
+        // Kings only
+        let (board, color) = Board::from_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(probe_wdl(&board, color), Some(Wdl::Draw));
+        init("");
+    }
+}
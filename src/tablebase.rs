@@ -0,0 +1,29 @@
+// src/tablebase.rs
+//
+// What the Syzygy and DTM tablebases share: the directories named by a
+// path option and the loading of their files.
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// The directories in `paths`, separated by ':' (';' on Windows); an empty
+// path or "<empty>" names none, which turns probing off
+pub fn directories(paths: &str) -> Vec<PathBuf> {
+    if paths == "<empty>" {
+        return Vec::new();
+    }
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    paths.split(separator).filter(|d| !d.is_empty()).map(PathBuf::from).collect()
+}
+
+// Maps the file at `path` and reads the table with `parse`; a file that
+// cannot be mapped or read is reported as corrupted
+pub fn load<T>(path: &Path, parse: impl FnOnce(Mmap) -> Option<T>) -> Option<T> {
+    // SAFETY: tablebase files are not expected to change while mapped
+    let map = File::open(path).and_then(|file| unsafe { Mmap::map(&file) });
+    let table = map.ok().and_then(parse);
+    if table.is_none() {
+        eprintln!("Corrupted tablebase file {}", path.display());
+    }
+    table
+}
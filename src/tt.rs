@@ -0,0 +1,175 @@
+// src/tt.rs
+use crate::Square;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Copy, Clone)]
+pub struct TTEntry {
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<(Square, Square)>,
+}
+
+// Each slot stores the key XORed with the data, so a torn write from another
+// thread shows up as a key mismatch instead of a corrupted entry.
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let wanted = (size_mb.max(1) * 1024 * 1024) / std::mem::size_of::<Slot>();
+        // Round down to a power of two so we can index with a mask
+        let count = 1usize << (usize::BITS - 1 - wanted.leading_zeros());
+        let slots = (0..count)
+            .map(|_| Slot {
+                key: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            })
+            .collect();
+        Self {
+            slots,
+            mask: count - 1,
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        let slot = &self.slots[key as usize & self.mask];
+        let data = slot.data.load(Ordering::Relaxed);
+        let stored_key = slot.key.load(Ordering::Relaxed);
+        if data == 0 || stored_key ^ data != key {
+            return None;
+        }
+        Some(unpack(data))
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        depth: i32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<(Square, Square)>,
+    ) {
+        let slot = &self.slots[key as usize & self.mask];
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+
+        // Keep deeper results for the same position unless we now have an exact score
+        if old_data != 0 && old_key == key {
+            let old = unpack(old_data);
+            if old.depth > depth && bound != Bound::Exact {
+                return;
+            }
+        }
+
+        let data = pack(depth, score, bound, best_move);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Layout: score (32 bits) | depth (8) | bound (2) | from (8) | to (8) | has move (1) | valid (1)
+fn pack(depth: i32, score: i32, bound: Bound, best_move: Option<(Square, Square)>) -> u64 {
+    let bound_bits: u64 = match bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    let (from, to, has_move) = match best_move {
+        Some((from, to)) => (from as u64, to as u64, 1u64),
+        None => (0, 0, 0),
+    };
+    (score as u32 as u64)
+        | ((depth.clamp(0, 255) as u64) << 32)
+        | (bound_bits << 40)
+        | (from << 42)
+        | (to << 50)
+        | (has_move << 58)
+        | (1 << 59)
+}
+
+fn unpack(data: u64) -> TTEntry {
+    let bound = match (data >> 40) & 0x3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    let best_move = if (data >> 58) & 1 == 1 {
+        Some((((data >> 42) & 0xff) as Square, ((data >> 50) & 0xff) as Square))
+    } else {
+        None
+    };
+    TTEntry {
+        depth: ((data >> 32) & 0xff) as i32,
+        score: data as u32 as i32,
+        bound,
+        best_move,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_keeps_every_field() {
+        for score in [0, 35, -35, i32::MAX - 1, -(i32::MAX - 1), i32::MIN + 1] {
+            for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+                for best_move in [None, Some((0x64, 0x44)), Some((0x77, 0x00))] {
+                    let entry = unpack(pack(12, score, bound, best_move));
+                    assert_eq!(entry.depth, 12);
+                    assert_eq!(entry.score, score);
+                    assert!(entry.bound == bound);
+                    assert_eq!(entry.best_move, best_move);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pack_clamps_the_depth() {
+        assert_eq!(unpack(pack(300, 0, Bound::Exact, None)).depth, 255);
+        assert_eq!(unpack(pack(-1, 0, Bound::Exact, None)).depth, 0);
+    }
+
+    #[test]
+    fn an_empty_entry_is_never_zero() {
+        assert_ne!(pack(0, 0, Bound::Exact, None), 0);
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let key = 0x463b96181691fc9c;
+        assert!(tt.probe(key).is_none());
+        tt.store(key, 5, -120, Bound::Upper, Some((0x64, 0x44)));
+        let entry = tt.probe(key).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.best_move), (5, -120, Some((0x64, 0x44))));
+        assert!(tt.probe(key ^ 1 << 40).is_none());
+    }
+
+    #[test]
+    fn deeper_entries_are_kept_unless_exact() {
+        let tt = TranspositionTable::new(1);
+        let key = 42;
+        tt.store(key, 6, 10, Bound::Lower, None);
+        tt.store(key, 3, 20, Bound::Upper, None);
+        assert_eq!(tt.probe(key).unwrap().depth, 6);
+        tt.store(key, 3, 30, Bound::Exact, None);
+        assert_eq!(tt.probe(key).unwrap().score, 30);
+    }
+}
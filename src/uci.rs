@@ -0,0 +1,476 @@
+// src/uci.rs
+use crate::async_search::{SearchEvent, SearchHandle, SearchLimits};
+use crate::book::Book;
+use crate::dtm;
+use crate::nnue;
+use crate::params::{self, EvalParams};
+use crate::skill::{Skill, MAX_ELO, MAX_SKILL, MIN_ELO};
+use crate::syzygy::{self, TB_WIN};
+use crate::{
+    coords_to_square, eval, format_move, is_mate_score, mate_plies, Board, Color, Engine, PieceType, Square, MAX_DEPTH,
+    MAX_PLY,
+};
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::time::Duration;
+
+// UCI has two ways to weaken the engine; UCI_LimitStrength selects UCI_Elo
+// over Skill Level
+struct StrengthOptions {
+    skill_level: u32,
+    limit_strength: bool,
+    elo: u32,
+}
+
+impl StrengthOptions {
+    fn skill(&self) -> Skill {
+        if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            Skill::new(self.skill_level)
+        }
+    }
+}
+
+// Stops a running search; its `bestmove` is printed before this returns
+fn stop_search(search: &mut Option<SearchHandle>) {
+    if let Some(s) = search.take() {
+        s.stop();
+        s.wait();
+    }
+}
+
+// Entered after the CLI has read the initial "uci" command
+pub fn run(engine: &mut Engine) {
+    print_id();
+    let mut board = Board::new();
+    board.setup_starting_position();
+    let mut color = Color::White;
+    let mut search: Option<SearchHandle> = None;
+    let mut strength = StrengthOptions {
+        skill_level: engine.skill.level(),
+        limit_strength: false,
+        elo: MAX_ELO,
+    };
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => print_id(),
+            Some(&"isready") => println!("readyok"),
+            Some(&"setoption") => set_option(engine, &mut strength, &tokens),
+            Some(&"ucinewgame") => {
+                stop_search(&mut search);
+                engine.resize_hash(engine.hash_mb);
+            }
+            Some(&"position") => {
+                stop_search(&mut search);
+                match parse_position(&tokens) {
+                    Some((b, c)) => {
+                        board = b;
+                        color = c;
+                    }
+                    None => println!("info string invalid position"),
+                }
+            }
+            Some(&"go") => {
+                stop_search(&mut search);
+                search = go(engine, &board, color, &tokens);
+            }
+            Some(&"ponderhit") => {
+                if let Some(s) = &search {
+                    s.ponderhit();
+                }
+            }
+            Some(&"stop") => {
+                stop_search(&mut search);
+            }
+            // Non-standard, like Stockfish's: prints the evaluation breakdown
+            Some(&"eval") => println!("{}", eval::trace(&board, color)),
+            Some(&"quit") => break,
+            _ => {}
+        }
+    }
+
+    stop_search(&mut search);
+}
+
+fn print_id() {
+    println!("id name Rust Chess Engine");
+    println!("id author AspireVenom");
+    println!("option name Hash type spin default {} min 1 max 1024", crate::DEFAULT_HASH_MB);
+    println!("option name Threads type spin default {} min 1 max 256", crate::DEFAULT_THREADS);
+    println!("option name Ponder type check default true");
+    println!("option name MultiPV type spin default 1 min 1 max 256");
+    println!("option name Skill Level type spin default {} min 0 max {}", MAX_SKILL, MAX_SKILL);
+    println!("option name UCI_LimitStrength type check default false");
+    println!("option name UCI_Elo type spin default {} min {} max {}", MAX_ELO, MIN_ELO, MAX_ELO);
+    println!("option name EvalParams type string default <empty>");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name UseNNUE type check default {}", nnue::active().is_some());
+    println!("option name SyzygyPath type string default <empty>");
+    println!("option name DtmPath type string default <empty>");
+    println!("option name BookFile type string default <empty>");
+    println!("option name BestBookMove type check default false");
+    println!("uciok");
+}
+
+fn set_option(engine: &mut Engine, strength: &mut StrengthOptions, tokens: &[&str]) {
+    let name_idx = tokens.iter().position(|&t| t == "name");
+    let value_idx = tokens.iter().position(|&t| t == "value");
+    let (Some(name_idx), Some(value_idx)) = (name_idx, value_idx) else {
+        return;
+    };
+    let name = tokens[name_idx + 1..value_idx].join(" ");
+    let value = tokens[value_idx + 1..].join(" ");
+
+    match name.to_ascii_lowercase().as_str() {
+        "hash" => {
+            if let Ok(mb) = value.parse::<usize>() {
+                engine.resize_hash(mb.max(1));
+            }
+        }
+        "threads" => {
+            if let Ok(n) = value.parse::<usize>() {
+                engine.threads = n.max(1);
+            }
+        }
+        "ponder" => engine.ponder = value == "true",
+        "multipv" => {
+            if let Ok(n) = value.parse::<usize>() {
+                engine.multipv = n.max(1);
+            }
+        }
+        "skill level" => {
+            if let Ok(level) = value.parse::<u32>() {
+                strength.skill_level = level.min(MAX_SKILL);
+                engine.skill = strength.skill();
+            }
+        }
+        "uci_limitstrength" => {
+            strength.limit_strength = value == "true";
+            engine.skill = strength.skill();
+        }
+        "uci_elo" => {
+            if let Ok(elo) = value.parse::<u32>() {
+                strength.elo = elo.clamp(MIN_ELO, MAX_ELO);
+                engine.skill = strength.skill();
+            }
+        }
+        "evalfile" => match nnue::load(&value) {
+            Ok(()) => {
+                println!("info string loaded network {}", value);
+                engine.resize_hash(engine.hash_mb);
+            }
+            Err(e) => println!("info string cannot load {}: {}", value, e),
+        },
+        "usennue" => {
+            let enable = value == "true";
+            if enable && !nnue::loaded() {
+                println!("info string no network loaded, set EvalFile first");
+            } else {
+                nnue::set_enabled(enable);
+                engine.resize_hash(engine.hash_mb);
+            }
+        }
+        "evalparams" => {
+            let loaded = if value.is_empty() || value == "<empty>" {
+                Ok(EvalParams::default())
+            } else {
+                EvalParams::load(&value)
+            };
+            match loaded {
+                Ok(p) => {
+                    params::set(p);
+                    // Stored scores were computed with the old weights
+                    engine.resize_hash(engine.hash_mb);
+                }
+                Err(e) => println!("info string cannot load {}: {}", value, e),
+            }
+        }
+        "syzygypath" => {
+            let found = syzygy::init(&value);
+            println!("info string found {} tablebases", found);
+            engine.resize_hash(engine.hash_mb);
+        }
+        "dtmpath" => {
+            let found = dtm::init(&value);
+            println!("info string found {} DTM tables", found);
+            engine.resize_hash(engine.hash_mb);
+        }
+        "bookfile" => {
+            if value.is_empty() || value == "<empty>" {
+                engine.book = None;
+            } else {
+                match Book::load(&value) {
+                    Ok(book) => {
+                        println!("info string loaded book {} ({} entries)", value, book.len());
+                        engine.book = Some(Arc::new(book));
+                    }
+                    Err(e) => println!("info string cannot load {}: {}", value, e),
+                }
+            }
+        }
+        "bestbookmove" => engine.best_book_move = value == "true",
+        _ => println!("info string unknown option {}", name),
+    }
+}
+
+// position [startpos | fen <fen>] [moves <move>...]
+fn parse_position(tokens: &[&str]) -> Option<(Board, Color)> {
+    let moves_idx = tokens.iter().position(|&t| t == "moves").unwrap_or(tokens.len());
+    let (mut board, mut color) = match tokens.get(1) {
+        Some(&"startpos") => {
+            let mut board = Board::new();
+            board.setup_starting_position();
+            (board, Color::White)
+        }
+        Some(&"fen") => Board::from_fen(&tokens[2..moves_idx].join(" "))?,
+        _ => return None,
+    };
+
+    for mv in tokens.iter().skip(moves_idx + 1) {
+        let (from, to) = parse_move(mv)?;
+        board.make_move(from, to);
+        color = color.opposite();
+    }
+    Some((board, color))
+}
+
+// Coordinate notation such as e2e4, castling as the king's move (e1g1) and
+// promotions with the piece (e7e8q); the engine only promotes to a queen
+fn parse_move(mv: &str) -> Option<(Square, Square)> {
+    if !(4..=5).contains(&mv.len()) || !mv.is_ascii() || mv.len() == 5 && !mv.ends_with('q') {
+        return None;
+    }
+    Some((coords_to_square(&mv[0..2])?, coords_to_square(&mv[2..4])?))
+}
+
+// A move in coordinate notation, with the queen for a promotion
+fn uci_move(board: &Board, (from, to): (Square, Square)) -> String {
+    let promotes = board.get_piece(from).is_some_and(|p| p.kind == PieceType::Pawn) && matches!(to >> 4, 0 | 7);
+    format!("{}{}", format_move((from, to)), if promotes { "q" } else { "" })
+}
+
+// Moves played one after another from the position
+fn uci_line(board: &Board, moves: &[(Square, Square)]) -> Vec<String> {
+    let mut board = board.clone();
+    moves
+        .iter()
+        .map(|&(from, to)| {
+            let text = uci_move(&board, (from, to));
+            board.make_move(from, to);
+            text
+        })
+        .collect()
+}
+
+// Kept on the clock for the GUI and the operating system
+const MOVE_OVERHEAD_MS: u64 = 50;
+// Moves the remaining time is shared over when the GUI does not say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+// Time for one move from the clock: an even share of what is left over the
+// moves to go plus most of the increment, always leaving the overhead
+fn time_budget(time: u64, increment: u64, moves_to_go: Option<u64>) -> Duration {
+    let share = time / moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1) + increment * 3 / 4;
+    Duration::from_millis(share.min(time.saturating_sub(MOVE_OVERHEAD_MS)).max(1))
+}
+
+// Starts the search, or answers at once with a book move; an infinite or
+// ponder search always searches. With a clock or movetime the time decides
+// when to stop unless a depth is given too.
+fn go(
+    engine: &Engine,
+    board: &Board,
+    color: Color,
+    tokens: &[&str],
+) -> Option<SearchHandle> {
+    let mut limits = SearchLimits {
+        depth: MAX_DEPTH,
+        ponder: false,
+        infinite: false,
+        nodes: None,
+        movetime: None,
+    };
+    let mut depth = None;
+    // Our clock and increment, then movestogo and movetime, in milliseconds
+    let (mut time, mut increment, mut moves_to_go, mut movetime) = (None, 0, None, None);
+    let (time_name, increment_name) = if color == Color::White { ("wtime", "winc") } else { ("btime", "binc") };
+    let mut i = 1;
+    while i < tokens.len() {
+        let value = tokens.get(i + 1).and_then(|v| v.parse::<i64>().ok()).map(|v| v.max(0) as u64);
+        match tokens[i] {
+            "depth" => {
+                if let Some(d) = tokens.get(i + 1).and_then(|d| d.parse::<i32>().ok()) {
+                    depth = Some(d.clamp(1, MAX_PLY));
+                }
+                i += 1;
+            }
+            name if name == time_name => {
+                time = value;
+                i += 1;
+            }
+            name if name == increment_name => {
+                increment = value.unwrap_or(0);
+                i += 1;
+            }
+            "movestogo" => {
+                moves_to_go = value;
+                i += 1;
+            }
+            "movetime" => {
+                movetime = value;
+                i += 1;
+            }
+            "wtime" | "btime" | "winc" | "binc" => i += 1,
+            "nodes" => {
+                limits.nodes = tokens.get(i + 1).and_then(|n| n.parse::<u64>().ok());
+                i += 1;
+            }
+            "ponder" => limits.ponder = true,
+            "infinite" => limits.infinite = true,
+            _ => {}
+        }
+        i += 1;
+    }
+    limits.movetime = match (movetime, time) {
+        (Some(ms), _) => Some(Duration::from_millis(ms.max(1))),
+        (None, Some(time)) => Some(time_budget(time, increment, moves_to_go)),
+        (None, None) => None,
+    };
+    limits.depth = depth.unwrap_or(if limits.movetime.is_some() { MAX_PLY } else { MAX_DEPTH });
+
+    if !limits.infinite
+        && !limits.ponder
+        && let Some(mv) = engine.book_move(board, color)
+    {
+        println!("info string book move");
+        println!("bestmove {}", uci_move(board, mv));
+        return None;
+    }
+    let root = board.clone();
+    Some(SearchHandle::start(engine, board, color, limits, move |event| {
+        println!("{}", event_line(&root, event))
+    }))
+}
+
+fn nps(nodes: u64, elapsed: Duration) -> u64 {
+    nodes * 1000 / (elapsed.as_millis() as u64).max(1)
+}
+
+// A score as UCI gives it: mates in moves, negative when the side to move
+// is mated, anything else in centipawns with tablebase wins kept below
+// TB_WIN
+fn uci_score(score: i32) -> String {
+    if is_mate_score(score) {
+        let moves = (mate_plies(score) + 1) / 2;
+        format!("mate {}", if score > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", score.clamp(-TB_WIN, TB_WIN))
+    }
+}
+
+// The line to print for an event; `board` is the position searched
+fn event_line(board: &Board, event: &SearchEvent) -> String {
+    match event {
+        SearchEvent::Iteration {
+            depth,
+            multipv,
+            score,
+            nodes,
+            elapsed,
+            pv,
+        } => {
+            let pv = uci_line(board, pv);
+            format!(
+                "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+                depth,
+                multipv,
+                uci_score(*score),
+                nodes,
+                nps(*nodes, *elapsed),
+                elapsed.as_millis(),
+                pv.join(" ")
+            )
+        }
+        SearchEvent::Progress {
+            depth,
+            nodes,
+            elapsed,
+            current_move,
+            current_move_number,
+        } => {
+            format!(
+                "info depth {} currmove {} currmovenumber {} nodes {} nps {} time {}",
+                depth,
+                uci_move(board, *current_move),
+                current_move_number,
+                nodes,
+                nps(*nodes, *elapsed),
+                elapsed.as_millis()
+            )
+        }
+        SearchEvent::Finished(result) => match (result.best_move, result.ponder_move) {
+            (Some(best), Some(ponder)) => {
+                let line = uci_line(board, &[best, ponder]);
+                format!("bestmove {} ponder {}", line[0], line[1])
+            }
+            (Some(best), None) => format!("bestmove {}", uci_move(board, best)),
+            (None, _) => "bestmove 0000".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn iteration(score: i32) -> String {
+        let mut board = Board::new();
+        board.setup_starting_position();
+        let event = SearchEvent::Iteration {
+            depth: 5,
+            multipv: 1,
+            score,
+            nodes: 2000,
+            elapsed: Duration::from_millis(500),
+            pv: vec![(0x64, 0x44), (0x14, 0x34)],
+        };
+        event_line(&board, &event)
+    }
+
+    #[test]
+    fn time_budgets() {
+        // 30 moves to go by default, plus three quarters of the increment
+        assert_eq!(time_budget(60_000, 0, None), Duration::from_millis(2000));
+        assert_eq!(time_budget(60_000, 1000, None), Duration::from_millis(2750));
+        assert_eq!(time_budget(60_000, 0, Some(10)), Duration::from_millis(6000));
+        // The last move before the time control keeps the overhead
+        assert_eq!(time_budget(1000, 0, Some(1)), Duration::from_millis(950));
+        assert_eq!(time_budget(20, 0, Some(1)), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn centipawn_scores() {
+        assert_eq!(iteration(35), "info depth 5 multipv 1 score cp 35 nodes 2000 nps 4000 time 500 pv e2e4 e7e5");
+        assert!(iteration(-120).contains(" score cp -120 "));
+    }
+
+    #[test]
+    fn mates_are_given_in_moves() {
+        // Mating in 3 plies, being mated in 4
+        assert!(iteration(i32::MAX - 1 - 3).contains(" score mate 2 "));
+        assert!(iteration(-(i32::MAX - 1) + 4).contains(" score mate -2 "));
+        // A DTM win 27 plies away
+        assert!(iteration(dtm::DTM_WIN - 27).contains(" score mate 14 "));
+    }
+
+    #[test]
+    fn tablebase_wins_stay_bounded() {
+        assert!(iteration(TB_WIN - 3).contains(&format!(" score cp {} ", TB_WIN - 3)));
+        assert!(iteration(-TB_WIN + 3).contains(&format!(" score cp {} ", -TB_WIN + 3)));
+    }
+}
@@ -0,0 +1,71 @@
+// src/zobrist.rs
+use crate::{Board, Color, PieceType};
+use lazy_static::lazy_static;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+struct ZobristKeys {
+    pieces: [[[u64; 128]; 6]; 2], // [color][piece type][0x88 square]
+    black_to_move: u64,
+    castling: [u64; 4],
+    en_passant_file: [u64; 8],
+}
+
+lazy_static! {
+    // Fixed seed so hashes are reproducible between runs
+    static ref KEYS: ZobristKeys = {
+        let mut rng = StdRng::seed_from_u64(0x5eed_c0ff_ee15_600d);
+        let mut pieces = [[[0u64; 128]; 6]; 2];
+        for color in pieces.iter_mut() {
+            for kind in color.iter_mut() {
+                for key in kind.iter_mut() {
+                    *key = rng.next_u64();
+                }
+            }
+        }
+        let black_to_move = rng.next_u64();
+        ZobristKeys {
+            pieces,
+            black_to_move,
+            castling: std::array::from_fn(|_| rng.next_u64()),
+            en_passant_file: std::array::from_fn(|_| rng.next_u64()),
+        }
+    };
+}
+
+pub fn hash(board: &Board, side_to_move: Color) -> u64 {
+    let mut key = 0;
+    for sq in 0u8..128 {
+        if !Board::is_valid(sq) {
+            continue;
+        }
+        if let Some(piece) = board.get_piece(sq) {
+            key ^= KEYS.pieces[piece.color as usize][piece.kind as usize][sq as usize];
+        }
+    }
+    if side_to_move == Color::Black {
+        key ^= KEYS.black_to_move;
+    }
+    for (right, &kept) in KEYS.castling.iter().zip(&board.castling) {
+        if kept {
+            key ^= right;
+        }
+    }
+    if let Some(sq) = board.en_passant {
+        key ^= KEYS.en_passant_file[(sq & 7) as usize];
+    }
+    key
+}
+
+// Key of the pawn structure alone, used by the pawn hash table
+pub fn pawn_hash(board: &Board) -> u64 {
+    let mut key = 0;
+    for sq in 0u8..128 {
+        if let Some(piece) = board.get_piece(sq)
+            && piece.kind == PieceType::Pawn
+        {
+            key ^= KEYS.pieces[piece.color as usize][PieceType::Pawn as usize][sq as usize];
+        }
+    }
+    key
+}